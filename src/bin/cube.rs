@@ -1,45 +1,24 @@
 /// Create a cube, a light source, and a camera, and position them such that the lit cube is
-/// visible from the camera.
+/// visible from the camera. Click in the window to look around with the mouse and move with the
+/// movement keys. The scene is described in `assets/scenes/cube.scene.ron`, and changes to the file
+/// are shown while the demo is running.
 use bevy::prelude::*;
+use bevy_demos::first_person_camera::FirstPersonCameraPlugin;
+use bevy_demos::scene_description::{DescribedScene, SceneDescriptionPlugin};
 
-fn setup(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    // Create and add a default material
-    let material_handle = materials.add(StandardMaterial {
-        base_color: Color::rgb(0.7, 0.6, 0.7),
-        ..Default::default()
-    });
+const SCENE_FILENAME: &str = "scenes/cube.scene.ron";
 
-    // Location to put cube
-    let cube_location = Vec3::new(0.0, 0.0, 4.0);
-
-    // Create a mesh from a `Cube` `shape`
-    commands.spawn(PbrBundle {
-        mesh: meshes.add(Mesh::from(shape::Cube::new(1.0))),
-        material: material_handle.clone(),
-        transform: Transform::from_translation(cube_location),
-        ..Default::default()
-    });
-
-    // Light
-    commands.spawn(PointLightBundle {
-        transform: Transform::from_xyz(2.0, 5.0, 2.0),
-        ..Default::default()
-    });
-
-    // Camera
-    commands.spawn(Camera3dBundle {
-        transform: Transform::from_xyz(1.0, 2.0, 0.0).looking_at(cube_location, Vec3::Y),
-        ..Default::default()
-    });
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(SpatialBundle::default())
+        .insert(DescribedScene::new(asset_server.load(SCENE_FILENAME)));
 }
 
 fn main() {
     App::new()
         .add_plugins(DefaultPlugins)
+        .add_plugins(FirstPersonCameraPlugin)
+        .add_plugins(SceneDescriptionPlugin)
         .add_systems(Startup, setup)
         .run();
 }
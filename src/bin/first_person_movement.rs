@@ -1,344 +1,811 @@
-/// Create a square to act as the ground and a few trees, then move the camera in response to mouse
-/// input and key presses.
-use bevy::input::mouse::MouseMotion;
-use bevy::math::{Quat, Vec3};
+/// Create hilly terrain to act as the ground and a forest of trees, then move the camera in
+/// response to mouse input and key presses. The camera starts out walking on the ground, and can
+/// jump or switch to flying freely. The movement keys can be changed on the screen opened by
+/// pressing F1, and default to the Dvorak preset. The sun rises and sets: press P to pause time,
+/// and ] or [ to make it pass faster or slower. Distant terrain and trees fade into the sky through
+/// fog: press - or = to bring the fog nearer or move it further away, and 0 to turn it off and on.
+///
+/// The world is divided into square chunks, each with its own terrain and trees. Chunks are
+/// generated as the camera approaches them and despawned once it is far away, so the world is
+/// endless but only a few chunks exist at a time. A chunk depends only on its position and the
+/// random seed, so it is the same each time it is generated. The world is different each run unless
+/// a seed is passed with `--seed <N>`.
+///
+/// Press Tab to enter edit mode, where a click places a tree on the ground under the crosshair and
+/// a right-click removes the tree under the crosshair. The keys 1 to 8 make the trees placed
+/// smaller or larger. The trees placed and removed form a layout, which F5 saves to
+/// `tree-layout.json` in the `bevy-demos` configuration directory and F9 loads again. A layout
+/// includes the seed of the world it was made in, which is regenerated if the seed differs.
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use bevy::math::Vec3;
 use bevy::prelude::*;
-use bevy::render::mesh::{Indices, Mesh};
-use bevy::render::render_resource::PrimitiveTopology;
 use bevy::window::{CursorGrabMode, PrimaryWindow};
+use bevy_demos::day_night::DayNightPlugin;
+use bevy_demos::first_person_camera::{
+    FirstPersonCamera, FirstPersonCameraPlugin, FirstPersonCameraSettings, MovementMode,
+};
+use bevy_demos::forest::{generate_forest, ForestSettings, Tree, TreeMeshes, CROWN_SIDES};
+use bevy_demos::key_bindings::rebinding_screen_closed;
+use bevy_demos::pyramid::Pyramid;
+use bevy_demos::rng::{self, SeededRng, SeededRngPlugin};
+use bevy_demos::settings::settings_path;
+use bevy_demos::sky::SkyPlugin;
+use bevy_demos::terrain::{Terrain, TerrainNoise, TerrainSettings};
+use bevy_demos::tree_lod::{spawn_tree, TreeLodPlugin, TreeOptions};
+use serde::{Deserialize, Serialize};
+
+/// The length of each side of a chunk.
+const CHUNK_SIZE: f32 = 32.;
+
+/// The number of terrain grid points along each side of a chunk.
+const CHUNK_RESOLUTION: usize = 33;
+
+/// Chunks up to this many chunks away from the one the camera is in are generated.
+const LOAD_DISTANCE: i32 = 3;
+
+/// Chunks more than this many chunks away from the one the camera is in are despawned. This is
+/// further than `LOAD_DISTANCE` so that walking back and forth across the edge of a chunk doesn't
+/// repeatedly generate and despawn the same chunks.
+const UNLOAD_DISTANCE: i32 = 4;
+
+/// The most chunks generated in one frame, so that moving quickly doesn't cause long pauses.
+const CHUNKS_PER_FRAME: usize = 2;
+
+const FONT_ASSET_FILENAME: &str = "fonts/FiraSans-Bold.ttf";
+
+/// The name of the file tree layouts are saved to, in the settings directory.
+const LAYOUT_FILENAME: &str = "tree-layout.json";
+
+const EDIT_MODE_KEY: KeyCode = KeyCode::Tab;
+const SAVE_LAYOUT_KEY: KeyCode = KeyCode::F5;
+const LOAD_LAYOUT_KEY: KeyCode = KeyCode::F9;
+const PLACE_TREE_BUTTON: MouseButton = MouseButton::Left;
+const REMOVE_TREE_BUTTON: MouseButton = MouseButton::Right;
+
+/// The furthest from the camera that trees can be placed or removed.
+const EDIT_REACH: f32 = 60.;
+
+/// The distance between the points tested when looking for where the crosshair meets the ground.
+/// The exact point is then found between the last point above the ground and the first below.
+const RAY_STEP: f32 = 0.5;
+
+/// Trees closer than this are taken to be the same tree, when matching the trees in a layout.
+const SAME_TREE_DISTANCE: f32 = 0.01;
+
+/// A tree placed in edit mode, as saved in a layout. Its height is taken from the terrain.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct PlacedTree {
+    x: f32,
+    z: f32,
+    trunk_height: f32,
+    trunk_width: f32,
+    crown_height: f32,
+    crown_width: f32,
+}
 
-// Time between each physics step.
-const TIME_STEP: f64 = 1.0 / 60.0;
+impl PlacedTree {
+    /// Returns the tree, standing at height `y`.
+    fn tree(&self, y: f32) -> Tree {
+        Tree {
+            trunk_height: self.trunk_height,
+            trunk_width: self.trunk_width,
+            crown_height: self.crown_height,
+            crown_width: self.crown_width,
+            location: Vec3::new(self.x, y, self.z),
+        }
+    }
+}
 
-// Player movement is per TIME_STEP, so must be changed if TIME_STEP is changed.
-const MOVE_PER_TIME_STEP: f32 = 0.15;
+/// The trees placed in edit mode, and the X and Z coordinates of the generated trees removed. The
+/// generated trees depend on the seed, so it is saved too.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+struct TreeLayout {
+    seed: u64,
+    placed: Vec<PlacedTree>,
+    removed: Vec<[f32; 2]>,
+}
 
-const MOUSE_SENSITIVITY: f32 = 500.;
+impl TreeLayout {
+    /// Returns `true` if the generated tree at `location` has been removed.
+    fn is_removed(&self, location: Vec3) -> bool {
+        let location = Vec2::new(location.x, location.z);
+        self.removed
+            .iter()
+            .any(|removed| Vec2::from(*removed).distance(location) < SAME_TREE_DISTANCE)
+    }
+}
 
+/// What's needed to generate chunks, and the chunks that currently exist.
 #[derive(Resource)]
-struct CameraOrientation {
-    x: f32,
-    y: f32,
+struct Chunks {
+    noise: TerrainNoise,
+    /// Combined with each chunk's coordinates to seed the placement of the chunk's trees.
+    forest_seed: u64,
+    /// The changes made to the generated trees in edit mode.
+    layout: TreeLayout,
+    material_handle_crown: Handle<StandardMaterial>,
+    material_handle_trunk: Handle<StandardMaterial>,
+    material_handle_ground: Handle<StandardMaterial>,
+    /// The entity holding the terrain of each chunk, with the chunk's trees as its children, keyed
+    /// by the chunk's coordinates.
+    loaded: HashMap<IVec2, Entity>,
 }
 
-impl Default for CameraOrientation {
+/// Whether edit mode is on, and the dimensions of the trees it places.
+#[derive(Resource)]
+struct TreeEditor {
+    enabled: bool,
+    trunk_height: f32,
+    trunk_width: f32,
+    crown_height: f32,
+    crown_width: f32,
+}
+
+impl Default for TreeEditor {
+    /// Trees are placed with the average dimensions of generated trees.
     fn default() -> Self {
-        Self { x: 0., y: 0. }
+        let forest = ForestSettings::default();
+        let middle = |range: std::ops::Range<f32>| (range.start + range.end) / 2.;
+        Self {
+            enabled: false,
+            trunk_height: middle(forest.trunk_height),
+            trunk_width: middle(forest.trunk_width),
+            crown_height: middle(forest.crown_height),
+            crown_width: middle(forest.crown_width),
+        }
     }
 }
 
-impl CameraOrientation {
-    fn into_quat(&self) -> Quat {
-        Quat::from_euler(EulerRot::YXZ, self.x, self.y, 0.)
-    }
+/// What is under the crosshair in edit mode.
+#[derive(Default, Resource)]
+struct Aim {
+    /// The point on the ground, if the ground is within reach and no tree is in the way.
+    ground: Option<Vec3>,
+    /// The tree, its location, and whether it was placed in edit mode rather than generated.
+    tree: Option<(Entity, Vec3, bool)>,
+    /// Whether the cursor was grabbed by the end of the previous frame. The click that grabs the
+    /// cursor doesn't place or remove a tree.
+    cursor_was_grabbed: bool,
+}
+
+/// The size of a tree, and whether it was placed in edit mode, so that it can be aimed at and
+/// removed.
+#[derive(Component)]
+struct EditableTree {
+    /// The radius of the widest part of the tree.
+    radius: f32,
+    height: f32,
+    placed: bool,
+}
+
+/// A label applied to the root node of the edit mode text and crosshair, so they can be removed.
+#[derive(Component)]
+struct EditorRoot;
+
+/// Returns the terrain noise and forest seed of the world generated from `seed`.
+fn generate_world(seed: u64) -> (TerrainNoise, u64) {
+    let mut rng = fastrand::Rng::with_seed(seed);
+    let noise = TerrainNoise::new(TerrainSettings::default(), rng.u64(..));
+    (noise, rng.u64(..))
+}
+
+/// Returns the coordinates of the chunk containing `x`, `z`.
+fn chunk_coords(x: f32, z: f32) -> IVec2 {
+    (Vec2::new(x, z) / CHUNK_SIZE).floor().as_ivec2()
 }
 
 fn setup(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    rng: Res<SeededRng>,
+    camera_settings: Res<FirstPersonCameraSettings>,
 ) {
     // Create and add a default material
-    let mut material_handle_crown = materials.add(StandardMaterial {
+    let material_handle_crown = materials.add(StandardMaterial {
         base_color: Color::rgb(0.3, 0.8, 0.3),
         ..Default::default()
     });
 
-    let mut material_handle_trunk = materials.add(StandardMaterial {
+    let material_handle_trunk = materials.add(StandardMaterial {
         base_color: Color::rgb(0.5, 0.3, 0.3),
         ..Default::default()
     });
 
-    create_tree(
-        &mut commands,
-        &mut meshes,
-        &mut material_handle_crown,
-        &mut material_handle_trunk,
-        1.,
-        0.3,
-        2.3,
-        0.8,
-        Vec3::new(0.5, 0., -8.),
-    );
-
-    create_tree(
-        &mut commands,
-        &mut meshes,
-        &mut material_handle_crown,
-        &mut material_handle_trunk,
-        1.,
-        0.3,
-        2.3,
-        0.8,
-        Vec3::new(-0.5, 0., -7.),
-    );
-
-    create_tree(
-        &mut commands,
-        &mut meshes,
-        &mut material_handle_crown,
-        &mut material_handle_trunk,
-        1.,
-        0.25,
-        3.5,
-        0.6,
-        Vec3::new(-1.5, 0., -10.),
-    );
-
-    // Ground
-    let ground_material_handle = materials.add(StandardMaterial {
+    let material_handle_ground = materials.add(StandardMaterial {
         base_color: Color::rgb(0.2, 0.4, 0.2),
         ..Default::default()
     });
 
-    commands.spawn(PbrBundle {
-        mesh: meshes.add(Mesh::from(shape::Quad::new(Vec2::new(100., 100.)))),
-        material: ground_material_handle.clone(),
-        transform: Transform::from_rotation(Quat::from_rotation_x(-std::f32::consts::PI / 2.)),
-        ..Default::default()
+    // The seed is logged at startup, so the world can be recreated by passing it with `--seed`.
+    let (noise, forest_seed) = generate_world(rng.seed());
+
+    // Camera, standing on the terrain with its eyes at the height walking keeps them at
+    let eye_level = noise.height_at(0., 0.) + camera_settings.eye_height;
+    commands
+        .spawn(Camera3dBundle {
+            transform: Transform::from_xyz(0., eye_level, 0.),
+            ..Default::default()
+        })
+        .insert(FirstPersonCamera::default());
+
+    commands.insert_resource(Chunks {
+        noise,
+        forest_seed,
+        layout: TreeLayout {
+            seed: rng.seed(),
+            ..Default::default()
+        },
+        material_handle_crown,
+        material_handle_trunk,
+        material_handle_ground,
+        loaded: HashMap::new(),
     });
+}
 
-    // Point light
-    commands.spawn(PointLightBundle {
-        transform: Transform::from_xyz(2., 5., 2.),
-        ..Default::default()
-    });
+/// Generates the chunks within `LOAD_DISTANCE` of the camera, nearest first, and despawns those
+/// beyond `UNLOAD_DISTANCE`.
+fn stream_chunks(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    tree_meshes: Res<TreeMeshes>,
+    mut chunks: ResMut<Chunks>,
+    cameras: Query<&Transform, With<FirstPersonCamera>>,
+) {
+    let Ok(camera) = cameras.get_single() else {
+        return;
+    };
+    let center = chunk_coords(camera.translation.x, camera.translation.z);
 
-    // Ambient light
-    commands.insert_resource(AmbientLight {
-        color: Color::rgb(1., 1., 0.8),
-        brightness: 0.3,
+    chunks.loaded.retain(|coords, entity| {
+        let keep = (*coords - center).abs().max_element() <= UNLOAD_DISTANCE;
+        if !keep {
+            commands.entity(*entity).despawn_recursive();
+        }
+        keep
     });
 
-    // Camera
-    commands.spawn(Camera3dBundle {
-        transform: Transform::from_xyz(0., 1., 0.),
-        ..Default::default()
-    });
+    let mut missing: Vec<IVec2> = (-LOAD_DISTANCE..=LOAD_DISTANCE)
+        .flat_map(|z| (-LOAD_DISTANCE..=LOAD_DISTANCE).map(move |x| center + IVec2::new(x, z)))
+        .filter(|coords| !chunks.loaded.contains_key(coords))
+        .collect();
+    missing.sort_by_key(|coords| (*coords - center).length_squared());
+
+    for coords in missing.into_iter().take(CHUNKS_PER_FRAME) {
+        let entity = spawn_chunk(&mut commands, &mut meshes, &tree_meshes, &chunks, coords);
+        chunks.loaded.insert(coords, entity);
+    }
 }
 
-/// Construct a tree with a pyramid for the crown and a shape::Box for the trunk. The height and
-/// width of each are passed as parameters. The bottom of the trunk is positioned at `location`.
-/// Materials for the crown and trunk are passed in `material_handle_crown' and
-/// `material_handle_trunk`. The tree and its meshes are added to `commands` and `meshes`.
-fn create_tree(
+/// Spawns the terrain and trees of the chunk at `coords`, and returns the entity holding them. The
+/// generated trees removed in edit mode are left out, and the trees placed are added.
+fn spawn_chunk(
     commands: &mut Commands,
     meshes: &mut ResMut<Assets<Mesh>>,
+    tree_meshes: &TreeMeshes,
+    chunks: &Chunks,
+    coords: IVec2,
+) -> Entity {
+    let min = coords.as_vec2() * CHUNK_SIZE;
+    let terrain = Terrain::from_noise(
+        &chunks.noise,
+        Rect::from_corners(min, min + Vec2::splat(CHUNK_SIZE)),
+        CHUNK_RESOLUTION,
+    );
+
+    // Keeping trees half the minimum spacing away from the edges keeps them far enough from the
+    // trees of neighbouring chunks.
+    let settings = ForestSettings::default();
+    let margin = Vec2::splat(settings.min_spacing / 2.);
+    let settings = ForestSettings {
+        area: Rect::from_corners(min + margin, min + Vec2::splat(CHUNK_SIZE) - margin),
+        ..settings
+    };
+    let seed = rng::hash(rng::hash(chunks.forest_seed ^ coords.x as u64) ^ coords.y as u64);
+    let forest = generate_forest(&settings, &mut fastrand::Rng::with_seed(seed));
+
+    commands
+        .spawn(PbrBundle {
+            mesh: meshes.add(Mesh::from(&terrain)),
+            material: chunks.material_handle_ground.clone(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            for mut tree in forest {
+                if chunks.layout.is_removed(tree.location) {
+                    continue;
+                }
+                tree.location.y = terrain.height_at(tree.location.x, tree.location.z);
+                create_tree(
+                    parent,
+                    tree_meshes,
+                    &chunks.material_handle_crown,
+                    &chunks.material_handle_trunk,
+                    &tree,
+                    false,
+                );
+            }
+
+            for placed in chunks.layout.placed.iter() {
+                if chunk_coords(placed.x, placed.z) == coords {
+                    create_tree(
+                        parent,
+                        tree_meshes,
+                        &chunks.material_handle_crown,
+                        &chunks.material_handle_trunk,
+                        &placed.tree(terrain.height_at(placed.x, placed.z)),
+                        true,
+                    );
+                }
+            }
+        })
+        .insert(terrain)
+        .id()
+}
+
+/// Construct a tree with `spawn_tree`, as a child of `parent`, with colliders on the trunk and
+/// crown so the camera cannot pass through them. Materials for the crown and trunk are passed in
+/// `material_handle_crown' and `material_handle_trunk`. `placed` is whether the tree was placed in
+/// edit mode.
+fn create_tree(
+    parent: &mut ChildBuilder,
+    tree_meshes: &TreeMeshes,
     material_handle_crown: &Handle<StandardMaterial>,
     material_handle_trunk: &Handle<StandardMaterial>,
-    trunk_height: f32,
-    trunk_width: f32,
-    crown_height: f32,
-    crown_width: f32,
-    location: Vec3,
+    tree: &Tree,
+    placed: bool,
 ) {
-    // Create a mesh for the tree trunk
-    commands.spawn(PbrBundle {
-        mesh: meshes.add(Mesh::from(shape::Box::new(
-            trunk_width,
-            trunk_height,
-            trunk_width,
-        ))),
-        material: material_handle_trunk.clone(),
-        transform: Transform::from_translation(location + Vec3::new(0., trunk_height / 2.0, 0.)),
-        ..Default::default()
+    let trunk_radius = tree.trunk_width * std::f32::consts::FRAC_1_SQRT_2;
+    let crown_radius = Pyramid::new(CROWN_SIDES, tree.crown_width, tree.crown_height).radius();
+
+    spawn_tree(
+        parent,
+        tree_meshes,
+        material_handle_crown,
+        material_handle_trunk,
+        tree,
+        TreeOptions {
+            colliders: true,
+            ..Default::default()
+        },
+    )
+    .insert(EditableTree {
+        radius: trunk_radius.max(crown_radius),
+        height: tree.trunk_height + tree.crown_height,
+        placed,
     });
-
-    // Create a mesh for the tree top
-    commands.spawn(PbrBundle {
-        mesh: meshes.add(Mesh::from(Pyramid::new(8, crown_width, crown_height))),
-        material: material_handle_crown.clone(),
-        transform: Transform::from_translation(location + Vec3::new(0., trunk_height, 0.)),
-        ..Default::default()
-    });
-}
-
-/// A pyramid with a base in the XZ plane centered on the origin and its apex along +Y.
-#[derive(Debug, Clone, Copy)]
-pub struct Pyramid {
-    pub sides: u32,
-    pub side_length: f32,
-    pub height: f32,
 }
 
-impl Pyramid {
-    pub fn new(sides: u32, side_length: f32, height: f32) -> Self {
-        assert!(sides > 2, "Pyramids must have 3 or more sides");
-        Pyramid {
-            sides,
-            side_length,
-            height,
-        }
+/// Turns edit mode on and off when `EDIT_MODE_KEY` is pressed.
+fn toggle_edit_mode(key: Res<Input<KeyCode>>, mut editor: ResMut<TreeEditor>) {
+    if key.just_pressed(EDIT_MODE_KEY) {
+        editor.enabled = !editor.enabled;
     }
 }
 
-impl Default for Pyramid {
-    fn default() -> Self {
-        Pyramid {
-            sides: 4,
-            side_length: 1.0,
-            height: 1.0,
-        }
+/// Returns `value` changed by `step` if `less` or `more` was just pressed, kept within `min..=max`.
+fn adjusted(
+    key: &Input<KeyCode>,
+    value: f32,
+    (less, more): (KeyCode, KeyCode),
+    step: f32,
+    (min, max): (f32, f32),
+) -> f32 {
+    if key.just_pressed(less) {
+        (value - step).max(min)
+    } else if key.just_pressed(more) {
+        (value + step).min(max)
+    } else {
+        value
     }
 }
 
-impl From<Pyramid> for Mesh {
-    fn from(p: Pyramid) -> Self {
-        let angle = std::f32::consts::PI * 2. / p.sides as f32;
-        let half_width = p.side_length / 2.;
-        let radius = half_width / f32::sin(angle / 2.);
-        let apex = Vec3::new(0., p.height, 0.);
-
-        // Calculate vertexes forming each face. The first vertex is located on the positive Z axis
-        // and faces are created counter-clockwise (looking down the Y axis towards negative Y.
-        let mut base_vertexes = Vec::with_capacity(p.sides as usize);
-        for s in 0..p.sides {
-            let a = angle * s as f32;
-            base_vertexes.push(Vec3::new(radius * f32::sin(a), 0., radius * f32::cos(a)));
-        }
-
-        let mut vertexes = Vec::new();
-        let mut bottom_vertexes = Vec::new();
+/// Changes the dimensions of the trees placed in edit mode when the number keys are pressed.
+fn adjust_tree_dimensions(key: Res<Input<KeyCode>>, mut editor: ResMut<TreeEditor>) {
+    if !editor.enabled {
+        return;
+    }
 
-        for s in 0..p.sides as usize {
-            // Determine normal by creating two vectors from the apex to the two other corners of
-            // this face, calculating their cross product and normalizing the result.
-            let b = &base_vertexes[s];
-            let c = &base_vertexes[(s + 1) % p.sides as usize];
+    let dimensions = [
+        editor.trunk_height,
+        editor.trunk_width,
+        editor.crown_height,
+        editor.crown_width,
+    ];
+    let trunk_height = adjusted(
+        &key,
+        editor.trunk_height,
+        (KeyCode::Key1, KeyCode::Key2),
+        0.1,
+        (0.2, 4.),
+    );
+    let trunk_width = adjusted(
+        &key,
+        editor.trunk_width,
+        (KeyCode::Key3, KeyCode::Key4),
+        0.05,
+        (0.05, 1.),
+    );
+    let crown_height = adjusted(
+        &key,
+        editor.crown_height,
+        (KeyCode::Key5, KeyCode::Key6),
+        0.2,
+        (0.4, 8.),
+    );
+    let crown_width = adjusted(
+        &key,
+        editor.crown_width,
+        (KeyCode::Key7, KeyCode::Key8),
+        0.1,
+        (0.2, 3.),
+    );
 
-            let ver_ab = *b - apex;
-            let ver_ac = *c - apex;
-            let normal = ver_ab.cross(ver_ac).normalize().to_array();
+    // Only assigning when a key was pressed keeps the text showing the dimensions from being
+    // rebuilt every frame.
+    if dimensions != [trunk_height, trunk_width, crown_height, crown_width] {
+        editor.trunk_height = trunk_height;
+        editor.trunk_width = trunk_width;
+        editor.crown_height = crown_height;
+        editor.crown_width = crown_width;
+    }
+}
 
-            vertexes.push((apex.to_array(), normal, [0.5, 1.]));
-            vertexes.push((b.to_array(), normal, [0., 0.]));
-            vertexes.push((c.to_array(), normal, [1., 0.]));
+/// Returns the height of the terrain at `x`, `z`, or `None` if its chunk hasn't been generated.
+fn terrain_height(chunks: &Chunks, terrains: &Query<&Terrain>, x: f32, z: f32) -> Option<f32> {
+    let entity = chunks.loaded.get(&chunk_coords(x, z))?;
+    terrains.get(*entity).ok().map(|t| t.height_at(x, z))
+}
 
-            bottom_vertexes.push(b);
+/// Returns the distance along the ray from `origin` in `direction` to where it enters the upright
+/// cylinder of `radius` and `height` standing on `base`, or `None` if it misses.
+fn ray_hits_cylinder(
+    origin: Vec3,
+    direction: Vec3,
+    base: Vec3,
+    radius: f32,
+    height: f32,
+) -> Option<f32> {
+    // The distances between which the ray is within `radius` of the axis, seen from above.
+    let offset = Vec2::new(origin.x - base.x, origin.z - base.z);
+    let horizontal = Vec2::new(direction.x, direction.z);
+    let a = horizontal.length_squared();
+    let b = 2. * offset.dot(horizontal);
+    let c = offset.length_squared() - radius * radius;
+    let (mut enter, mut exit) = if a < f32::EPSILON {
+        if c > 0. {
+            return None;
         }
-
-        // Translate a `Vec3` position on the bottom face to u, v coordinates returned as an
-        // array. `limit` is the largest absolute distance that the position can be from the
-        // origin. This function therefore translates -limit..=limit to 0..=1 for both axes.
-        fn xz_to_uv(pos: &Vec3, limit: f32) -> [f32; 2] {
-            [
-                (pos.x + limit) / (limit * 2.),
-                (pos.z + limit) / (limit * 2.),
-            ]
+        (f32::NEG_INFINITY, f32::INFINITY)
+    } else {
+        let discriminant = b * b - 4. * a * c;
+        if discriminant < 0. {
+            return None;
         }
-
-        // Vertexes for the bottom face were saved in a counter-clockwise direction when looking
-        // from +Y to the origin. Their order is reversed so they are CCW when looking at the
-        // bottom face of the pyramid from -Y.
-        bottom_vertexes.reverse();
-
-        // The last vertex in the list is the one nearest +Z. It is used as the first vertex in all
-        // triangles forming the bottom face.
-        let vertex_nearest_pos_z = bottom_vertexes.pop().unwrap();
-        let texture_bound = vertex_nearest_pos_z.z;
-
-        for pair in bottom_vertexes.windows(2) {
-            let normal = [0., -1., 0.];
-
-            vertexes.push((vertex_nearest_pos_z.to_array(), normal, [0.5, 1.]));
-            vertexes.push((pair[0].to_array(), normal, xz_to_uv(pair[0], texture_bound)));
-            vertexes.push((pair[1].to_array(), normal, xz_to_uv(pair[1], texture_bound)));
+        let root = discriminant.sqrt();
+        ((-b - root) / (2. * a), (-b + root) / (2. * a))
+    };
+
+    // Narrow them to the distances between which the ray is between the bottom and top.
+    let (bottom, top) = (base.y - origin.y, base.y + height - origin.y);
+    if direction.y.abs() < f32::EPSILON {
+        if bottom > 0. || top < 0. {
+            return None;
         }
+    } else {
+        let (near, far) = (bottom / direction.y, top / direction.y);
+        enter = enter.max(near.min(far));
+        exit = exit.min(near.max(far));
+    }
 
-        let num_vertexes = 6 * p.sides - 6;
-
-        let mut positions = Vec::with_capacity(num_vertexes as usize);
-        let mut normals = Vec::with_capacity(num_vertexes as usize);
-        let mut uvs = Vec::with_capacity(num_vertexes as usize);
+    (enter <= exit && exit >= 0.).then_some(enter.max(0.))
+}
 
-        for (position, normal, uv) in vertexes.iter() {
-            positions.push(*position);
-            normals.push(*normal);
-            uvs.push(*uv);
+/// Finds the ground and tree under the crosshair in edit mode. Whichever is nearer hides the other.
+fn aim(
+    editor: Res<TreeEditor>,
+    chunks: Res<Chunks>,
+    terrains: Query<&Terrain>,
+    cameras: Query<&GlobalTransform, With<FirstPersonCamera>>,
+    trees: Query<(Entity, &EditableTree, &GlobalTransform)>,
+    mut aim: ResMut<Aim>,
+) {
+    aim.ground = None;
+    aim.tree = None;
+    let Ok(camera) = cameras.get_single() else {
+        return;
+    };
+    if !editor.enabled {
+        return;
+    }
+    let origin = camera.translation();
+    let direction = camera.forward();
+    let is_below_ground = |distance: f32| {
+        let point = origin + direction * distance;
+        terrain_height(&chunks, &terrains, point.x, point.z).map(|height| point.y <= height)
+    };
+
+    // Step along the ray until it passes below the ground, then narrow down where it crossed.
+    let mut ground_distance = None;
+    let mut above = 0.;
+    let mut distance = 0.;
+    while distance <= EDIT_REACH {
+        match is_below_ground(distance) {
+            None => break,
+            Some(true) => {
+                let mut below = distance;
+                for _ in 0..12 {
+                    let middle = (above + below) / 2.;
+                    if is_below_ground(middle) == Some(true) {
+                        below = middle;
+                    } else {
+                        above = middle;
+                    }
+                }
+                ground_distance = Some(below);
+                break;
+            }
+            Some(false) => {
+                above = distance;
+                distance += RAY_STEP;
+            }
         }
+    }
 
-        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
-        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
-        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
-        mesh.set_indices(Some(Indices::U32((0..num_vertexes).collect())));
-        mesh
+    let nearest_tree = trees
+        .iter()
+        .filter_map(|(entity, tree, transform)| {
+            let base = transform.translation();
+            ray_hits_cylinder(origin, direction, base, tree.radius, tree.height)
+                .map(|distance| (distance, (entity, base, tree.placed)))
+        })
+        .filter(|(distance, _)| *distance <= EDIT_REACH)
+        .min_by(|(a, _), (b, _)| a.total_cmp(b));
+
+    match (nearest_tree, ground_distance) {
+        (Some((tree_distance, _)), Some(ground)) if tree_distance > ground => {
+            aim.ground = Some(origin + direction * ground);
+        }
+        (Some((_, tree)), _) => aim.tree = Some(tree),
+        (None, Some(ground)) => aim.ground = Some(origin + direction * ground),
+        (None, None) => {}
     }
 }
 
-fn movement_system(
-    mut primary_query: Query<&mut Window, With<PrimaryWindow>>,
-    mut motion_evr: EventReader<MouseMotion>,
-    mut camera_orientation: ResMut<CameraOrientation>,
-    btn: Res<Input<MouseButton>>,
-    key: Res<Input<KeyCode>>,
-    mut query: Query<(&Camera, &mut Transform)>,
+/// Places a tree on the ground under the crosshair when `PLACE_TREE_BUTTON` is clicked, and removes
+/// the tree under the crosshair when `REMOVE_TREE_BUTTON` is clicked, recording the change in the
+/// layout.
+fn edit_trees(
+    mut commands: Commands,
+    buttons: Res<Input<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    editor: Res<TreeEditor>,
+    mut aim: ResMut<Aim>,
+    mut chunks: ResMut<Chunks>,
+    tree_meshes: Res<TreeMeshes>,
 ) {
-    let (_camera, mut transform) = query.single_mut();
-
-    let mut window = primary_query
-        .get_single_mut()
-        .expect("Internal error: cannot locate primary window");
+    let grabbed = windows
+        .get_single()
+        .is_ok_and(|window| window.cursor.grab_mode != CursorGrabMode::None);
+    let was_grabbed = aim.cursor_was_grabbed;
+    aim.cursor_was_grabbed = grabbed;
+    if !grabbed || !was_grabbed {
+        return;
+    }
 
-    if btn.just_pressed(MouseButton::Left) {
-        window.cursor.grab_mode = CursorGrabMode::Confined;
-        window.cursor.visible = false;
+    if let (true, Some(point)) = (buttons.just_pressed(PLACE_TREE_BUTTON), aim.ground) {
+        let placed = PlacedTree {
+            x: point.x,
+            z: point.z,
+            trunk_height: editor.trunk_height,
+            trunk_width: editor.trunk_width,
+            crown_height: editor.crown_height,
+            crown_width: editor.crown_width,
+        };
+        if let Some(&chunk) = chunks.loaded.get(&chunk_coords(point.x, point.z)) {
+            commands.entity(chunk).with_children(|parent| {
+                create_tree(
+                    parent,
+                    &tree_meshes,
+                    &chunks.material_handle_crown,
+                    &chunks.material_handle_trunk,
+                    &placed.tree(point.y),
+                    true,
+                );
+            });
+        }
+        chunks.layout.placed.push(placed);
     }
 
-    if key.just_pressed(KeyCode::Escape) {
-        window.cursor.grab_mode = CursorGrabMode::None;
-        window.cursor.visible = true;
+    if let (true, Some((entity, location, placed))) =
+        (buttons.just_pressed(REMOVE_TREE_BUTTON), aim.tree)
+    {
+        let location = Vec2::new(location.x, location.z);
+        if placed {
+            chunks
+                .layout
+                .placed
+                .retain(|tree| Vec2::new(tree.x, tree.z).distance(location) >= SAME_TREE_DISTANCE);
+        } else {
+            chunks.layout.removed.push(location.to_array());
+        }
+        commands.entity(entity).despawn_recursive();
+        aim.tree = None;
     }
+}
 
-    if window.cursor.visible {
-        return;
+/// Saves the layout to `LAYOUT_FILENAME` in the settings directory, and returns the file's path.
+fn save_layout(layout: &TreeLayout) -> io::Result<PathBuf> {
+    let path = settings_path(LAYOUT_FILENAME).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "cannot determine the configuration directory",
+        )
+    })?;
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
     }
+    fs::write(&path, serde_json::to_string_pretty(layout)?)?;
+    Ok(path)
+}
 
-    let mut movement = Vec3::ZERO;
+/// Returns the layout saved by `save_layout`.
+fn load_layout() -> io::Result<TreeLayout> {
+    let path = settings_path(LAYOUT_FILENAME).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "cannot determine the configuration directory",
+        )
+    })?;
 
-    if key.pressed(KeyCode::A) {
-        movement.x -= 1.;
-    }
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
 
-    if key.pressed(KeyCode::E) {
-        movement.x += 1.;
+/// Saves the layout when `SAVE_LAYOUT_KEY` is pressed, and loads it when `LOAD_LAYOUT_KEY` is
+/// pressed. Loading regenerates every chunk, and the whole world if the layout was made with a
+/// different seed, lifting the camera if it would be below the new ground.
+fn save_or_load_layout(
+    mut commands: Commands,
+    key: Res<Input<KeyCode>>,
+    mut chunks: ResMut<Chunks>,
+    mut cameras: Query<&mut Transform, With<FirstPersonCamera>>,
+) {
+    if key.just_pressed(SAVE_LAYOUT_KEY) {
+        match save_layout(&chunks.layout) {
+            Ok(path) => info!("Saved the tree layout to {}", path.display()),
+            Err(e) => warn!("Cannot save the tree layout: {}", e),
+        }
     }
 
-    if key.pressed(KeyCode::Comma) {
-        movement.z -= 1.;
+    if !key.just_pressed(LOAD_LAYOUT_KEY) {
+        return;
     }
-
-    if key.pressed(KeyCode::ShiftLeft) {
-        movement.y += 1.;
+    let layout = match load_layout() {
+        Ok(layout) => layout,
+        Err(e) => {
+            warn!("Cannot load the tree layout: {}", e);
+            return;
+        }
+    };
+
+    if layout.seed != chunks.layout.seed {
+        info!(
+            "The tree layout was made with seed {}, so the world is regenerated from it",
+            layout.seed
+        );
+        (chunks.noise, chunks.forest_seed) = generate_world(layout.seed);
+    }
+    chunks.layout = layout;
+    for (_, entity) in chunks.loaded.drain() {
+        commands.entity(entity).despawn_recursive();
     }
 
-    if key.pressed(KeyCode::J) {
-        movement.y -= 1.;
+    for mut transform in cameras.iter_mut() {
+        let ground = chunks
+            .noise
+            .height_at(transform.translation.x, transform.translation.z);
+        transform.translation.y = transform.translation.y.max(ground + 1.);
     }
+}
 
-    if key.pressed(KeyCode::O) {
-        movement.z += 1.;
+/// Shows a crosshair and the edit mode keys while edit mode is on, rebuilding them whenever the
+/// dimensions of the trees placed change.
+fn show_editor(
+    mut commands: Commands,
+    editor: Res<TreeEditor>,
+    asset_server: Res<AssetServer>,
+    roots: Query<Entity, With<EditorRoot>>,
+) {
+    if !editor.is_changed() {
+        return;
     }
 
-    let adjusted_movement = transform.rotation * movement;
-    transform.translation.x += adjusted_movement.x * MOVE_PER_TIME_STEP;
-    transform.translation.z += adjusted_movement.z * MOVE_PER_TIME_STEP;
-    transform.translation.y += adjusted_movement.y * MOVE_PER_TIME_STEP;
+    for entity in roots.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
 
-    for event in motion_evr.read() {
-        camera_orientation.x -= event.delta.x / MOUSE_SENSITIVITY;
-        camera_orientation.y -= event.delta.y / MOUSE_SENSITIVITY;
+    if !editor.enabled {
+        return;
     }
 
-    transform.rotation = camera_orientation.into_quat().normalize();
+    let text_style = |font_size| TextStyle {
+        font: asset_server.load(FONT_ASSET_FILENAME),
+        font_size,
+        color: Color::WHITE,
+    };
+    let help = format!(
+        "Edit mode (Tab to leave)\n\
+         Click: place a tree\n\
+         Right-click: remove a tree\n\
+         1 / 2: trunk height {:.1}\n\
+         3 / 4: trunk width {:.2}\n\
+         5 / 6: crown height {:.1}\n\
+         7 / 8: crown width {:.1}\n\
+         F5: save layout\n\
+         F9: load layout",
+        editor.trunk_height, editor.trunk_width, editor.crown_height, editor.crown_width
+    );
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(EditorRoot)
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section("+", text_style(30.0)));
+            parent.spawn(
+                TextBundle::from_section(help, text_style(20.0)).with_style(Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(10.0),
+                    top: Val::Px(10.0),
+                    ..Default::default()
+                }),
+            );
+        });
 }
 
 fn main() {
     App::new()
-        .insert_resource(CameraOrientation::default())
-        .insert_resource(Time::<Fixed>::from_seconds(TIME_STEP))
         .add_plugins(DefaultPlugins)
+        .add_plugins(FirstPersonCameraPlugin)
+        .add_plugins(SeededRngPlugin)
+        .add_plugins(TreeLodPlugin)
+        .add_plugins(DayNightPlugin)
+        .add_plugins(SkyPlugin)
+        .insert_resource(FirstPersonCameraSettings {
+            mode: MovementMode::Walk,
+            ..Default::default()
+        })
+        .init_resource::<TreeEditor>()
+        .init_resource::<Aim>()
         .add_systems(Startup, setup)
-        .add_systems(Update, movement_system)
+        .add_systems(
+            Update,
+            (
+                stream_chunks,
+                (
+                    toggle_edit_mode,
+                    adjust_tree_dimensions,
+                    aim,
+                    edit_trees,
+                    save_or_load_layout,
+                    show_editor,
+                )
+                    .chain()
+                    .run_if(rebinding_screen_closed),
+            )
+                .chain(),
+        )
         .run();
 }
@@ -1,64 +1,750 @@
 /// A basic implementation of the classic table-top strategy game that consists of trying to get a
 /// line of 4 pieces vertically, horizontally or diagonally before your computer opponent. The
-/// computer randomly picks a column for each of its moves, so it shouldn't be hard to beat!
+/// computer plays one of three strategies, chosen in the menu: Random picks a column at random,
+/// Greedy also completes its own lines and blocks the human's, and Cautious also avoids playing
+/// below a cell where the human could then complete a line. Games can be played one at a time or
+/// as a best-of-N match, which ends as soon as the games left can't change who wins it.
+///
+/// Moves are made by clicking on a column, or on a touchscreen by tapping it. While a finger is on
+/// the board, a faded piece shows where it would land, and the piece is dropped in the column the
+/// finger is lifted over.
+///
+/// The game starts with a menu for choosing the length of the match, how hard the computer is to
+/// beat, who moves first, the size of the board and how pieces are drawn.
+///
+/// The menu also leads to a position editor, where clicking a cell changes the piece in it, and
+/// pressing Enter plays a game from the position once it is one that could arise in a game. Escape
+/// returns to the menu from the editor and from puzzle mode. In puzzle mode, each puzzle from
+/// `assets/puzzles/fourline.txt` must be won within a given number of moves, however the computer
+/// defends. A puzzle fails as soon as a move lets the computer escape.
+///
+/// Press T to toggle an overlay that marks the cells where either player could complete a line
+/// with their next move. Press U to take back your last move, or R to restart the game. Press P to
+/// switch between piece themes, some of which use shapes rather than color to tell the players
+/// apart. The chosen theme is remembered between runs.
+///
+/// Text is shown in the language of the system locale if fourline has been translated into it, or
+/// in English otherwise. The language can also be chosen in the menu, and is remembered between
+/// runs. Translations are in `assets/locales/fourline`.
+///
+/// Command line options:
+///   --best-of <N>       Preselect a match of N games in the menu.
+///   --ai <STRATEGY>     Preselect the strategy used by the computer in the menu: random (default),
+///                       greedy or cautious.
+///   --tournament        Play a round-robin tournament between all computer strategies and print
+///                       the standings, without opening a window.
+///   --theme <THEME>     Draw pieces using the given theme for this run: classic, color-safe,
+///                       rings or symbols.
+///   --language <CODE>   Show text in the language with the given two letter code for this run,
+///                       e.g., en or fr.
+///   --log-games <DIR>   Write a JSON record of each game to a file in DIR, listing the board,
+///                       players, every move with when it was made and how long the player took
+///                       to choose it, and the result.
+///   --seed <N>          Seed the random number generator, to replay an earlier run exactly.
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bevy::asset::io::file::FileAssetReader;
 use bevy::prelude::*;
+use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
 use bevy::window::{PrimaryWindow, WindowResolution};
+use bevy_demos::localization::{self, StringTable};
+use bevy_demos::rng::{self, SeededRng, SeededRngPlugin};
+use bevy_demos::settings;
+use serde::Serialize;
 
 const WINDOW_WIDTH: f32 = 700.0;
 const WINDOW_HEIGHT: f32 = 700.0;
-const BOARD_COLUMNS: usize = 7;
-const BOARD_ROWS: usize = 6;
 const SPRITE_FILENAME: &str = "sprites/fourline.png";
+const FONT_ASSET_FILENAME: &str = "fonts/FiraSans-Bold.ttf";
 const SPRITE_WIDTH: usize = 80;
 const SPRITE_HEIGHT: usize = 80;
+const SOLID_PIECE_TILE: usize = 1;
+const RING_PIECE_TILE: usize = 2;
+const CROSS_PIECE_TILE: usize = 3;
+const PUZZLE_FILENAME: &str = "puzzles/fourline.txt";
+const SETTINGS_FILENAME: &str = "fourline.cfg";
+const THEME_SETTING: &str = "theme";
+const LANGUAGE_SETTING: &str = "language";
+const LOCALE_NAME: &str = "fourline";
+const THREAT_MARKER_SIZE: f32 = 20.0;
+const DOUBLE_THREAT_MARKER_SIZE: f32 = 36.0;
+const THREAT_OVERLAY_KEY: KeyCode = KeyCode::T;
+const NEXT_GAME_KEY: KeyCode = KeyCode::Space;
+const UNDO_KEY: KeyCode = KeyCode::U;
+const RESTART_KEY: KeyCode = KeyCode::R;
+const THEME_KEY: KeyCode = KeyCode::P;
+const EDITOR_PLAY_KEY: KeyCode = KeyCode::Return;
+const MENU_KEY: KeyCode = KeyCode::Escape;
+const PUZZLE_STRATEGY: Strategy = Strategy::Cautious;
+const MATCH_LENGTHS: [u32; 4] = [1, 3, 5, 7];
+const TEXT_COLOR: Color = Color::rgb(0.6, 0.6, 1.0);
+const BUTTON_COLOR: Color = Color::rgb(0.2, 0.2, 0.35);
+const HOVERED_BUTTON_COLOR: Color = Color::rgb(0.3, 0.3, 0.5);
+const TOURNAMENT_GAMES_PER_PAIRING: u32 = 20;
 
 type Cell = Option<Player>;
 
+/// Matches the entities the position editor replaces whenever the position changes.
+type EditorRedrawFilter = Or<(With<Piece>, With<EditorStatus>)>;
+
+/// Matches the entities drawn on the board during a game, which are removed if it is abandoned.
+type GameBoardFilter = Or<(With<Piece>, With<TouchPreview>, With<ThreatMarker>)>;
+
 /// A label that is applied to the primary camera to make it more convenient to reference.
 #[derive(Component)]
 struct PrimaryCamera;
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// A label applied to each cell of the board, so the board can be removed when returning to the
+/// menu.
+#[derive(Component)]
+struct BoardTile;
+
+/// Applied to each player piece to record the cell it occupies and who played it, so it can be
+/// removed when a move is taken back or a new game starts, and redrawn when the theme changes.
+#[derive(Component)]
+struct Piece {
+    column: usize,
+    row: usize,
+    player: Player,
+}
+
+/// A label applied to the faded piece that shows where the human's piece would land while the
+/// board is being touched.
+#[derive(Component)]
+struct TouchPreview;
+
+/// A label applied to the messages shown when a game ends, so they can be removed when a new game
+/// starts.
+#[derive(Component)]
+struct GameOverText;
+
+/// A label applied to the root node of the menu, so the menu can be removed when a match starts.
+#[derive(Component)]
+struct MenuRoot;
+
+/// A label applied to the messages shown in the position editor, so they can be removed when
+/// leaving it.
+#[derive(Component)]
+struct EditorText;
+
+/// A label applied to the message saying whether the position in the editor can be played, so it
+/// can be replaced when the position changes.
+#[derive(Component)]
+struct EditorStatus;
+
+/// A label applied to the message naming the puzzle being played.
+#[derive(Component)]
+struct PuzzleText;
+
+/// A label applied to the menu's title, so it can be updated when the language changes.
+#[derive(Component)]
+struct MenuTitle;
+
+/// The buttons in the menu. All but `Editor`, `Puzzles` and `Play` cycle through the values of one
+/// setting.
+#[derive(Clone, Copy, Component, Debug, Eq, PartialEq)]
+enum MenuButton {
+    Mode,
+    Difficulty,
+    Starter,
+    Board,
+    Theme,
+    Language,
+    Editor,
+    Puzzles,
+    Play,
+}
+
+impl MenuButton {
+    const ALL: [MenuButton; 9] = [
+        MenuButton::Mode,
+        MenuButton::Difficulty,
+        MenuButton::Starter,
+        MenuButton::Board,
+        MenuButton::Theme,
+        MenuButton::Language,
+        MenuButton::Editor,
+        MenuButton::Puzzles,
+        MenuButton::Play,
+    ];
+
+    /// Returns the text shown on this button in the language of `strings`, which includes the
+    /// current value of its setting.
+    fn label(self, choices: &MenuChoices, theme: PieceTheme, strings: &StringTable) -> String {
+        match self {
+            MenuButton::Mode if choices.best_of == 1 => strings.get("menu.mode.single").to_string(),
+            MenuButton::Mode => strings.format("menu.mode.best_of", &[&choices.best_of]),
+            MenuButton::Difficulty => strings.format(
+                "menu.difficulty",
+                &[&strings.get(choices.computer_strategy.difficulty_key())],
+            ),
+            MenuButton::Starter => match choices.starting_player {
+                Player::Computer => strings.get("menu.first_move.computer").to_string(),
+                Player::Human => strings.get("menu.first_move.human").to_string(),
+            },
+            MenuButton::Board => strings.format(
+                "menu.board",
+                &[
+                    &strings.get(choices.board.name_key()),
+                    &choices.board.columns(),
+                    &choices.board.rows(),
+                ],
+            ),
+            MenuButton::Theme => {
+                let name = strings.get(&format!("theme.{}", theme.name())).to_string();
+                strings.format("menu.theme", &[&name])
+            }
+            MenuButton::Language => {
+                strings.format("menu.language", &[&strings.get("language.name")])
+            }
+            MenuButton::Editor => strings.get("menu.editor").to_string(),
+            MenuButton::Puzzles => strings.get("menu.puzzles").to_string(),
+            MenuButton::Play => strings.get("menu.play").to_string(),
+        }
+    }
+}
+
+/// Applied to the text of each menu button, so the text can be updated when a setting changes.
+#[derive(Component)]
+struct MenuLabel(MenuButton);
+
+/// The texture atlas holding the board and piece tiles.
+#[derive(Resource)]
+struct SpriteAtlas(Handle<TextureAtlas>);
+
+/// The settings chosen in the menu, used to set up each match.
+#[derive(Resource)]
+struct MenuChoices {
+    best_of: u32,
+    computer_strategy: Strategy,
+    starting_player: Player,
+    board: BoardVariant,
+}
+
+/// A label applied to each marker drawn by the threat overlay, so they can be found and removed.
+#[derive(Component)]
+struct ThreatMarker;
+
+/// The computer's next move while it is being chosen in the background. `indicator` is the message
+/// shown while this takes more than a frame. Removing this resource drops `task`, which cancels
+/// it.
+#[derive(Resource)]
+struct ComputerThinking {
+    task: Task<usize>,
+    indicator: Option<Entity>,
+}
+
+/// Writes a record of each game to a JSON file in `dir`. Only present if enabled with the
+/// `--log-games` command line option.
+#[derive(Resource)]
+struct GameLog {
+    dir: PathBuf,
+    /// The game in progress, or `None` between games.
+    record: Option<GameRecord>,
+    /// The moves on the board when the record was last updated, in the order they were played.
+    logged_moves: Vec<(usize, usize, Player)>,
+    /// The time, as measured by `Time::elapsed`, at which the game started.
+    started: Duration,
+    /// The time, as measured by `Time::elapsed`, at which the player to move started their turn.
+    turn_started: Duration,
+}
+
+impl GameLog {
+    /// Creates a log that writes games to files in `dir`, which is created if necessary.
+    fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            record: None,
+            logged_moves: Vec::new(),
+            started: Duration::ZERO,
+            turn_started: Duration::ZERO,
+        }
+    }
+}
+
+/// Records each game in a `GameLog` writing to `dir`. Does nothing if `dir` is `None`.
+struct GameLogPlugin {
+    dir: Option<PathBuf>,
+}
+
+impl Plugin for GameLogPlugin {
+    fn build(&self, app: &mut App) {
+        let Some(dir) = &self.dir else {
+            return;
+        };
+
+        app.insert_resource(GameLog::new(dir.clone()))
+            .add_systems(OnEnter(GameState::Playing), start_game_record)
+            .add_systems(
+                Update,
+                record_moves
+                    .after(undo_or_restart)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                OnEnter(GameState::GameOver),
+                write_game_record.after(record_game_outcome),
+            );
+    }
+}
+
+/// Everything that happened in one game, as written to the log.
+#[derive(Serialize)]
+struct GameRecord {
+    /// When the game started, in milliseconds since the Unix epoch.
+    start_time_ms: u64,
+    board: BoardVariant,
+    columns: usize,
+    rows: usize,
+    first_player: Player,
+    computer_strategy: Strategy,
+    /// The pieces on the board when the game started, one string per row from the top down,
+    /// using X for the human's pieces, O for the computer's and . for empty cells.
+    start_position: Vec<String>,
+    /// The seed of the random number generator, which allows the run to be replayed.
+    seed: u64,
+    events: Vec<GameEvent>,
+    outcome: Option<GameOutcome>,
+}
+
+/// A change to the board during a game. Times are in milliseconds since the game started.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+enum GameEvent {
+    /// `player` played a piece, taking `thinking_time_ms` since the start of their turn.
+    Move {
+        player: Player,
+        column: usize,
+        row: usize,
+        time_ms: u64,
+        thinking_time_ms: u64,
+    },
+    /// The piece `player` played at `column` and `row` was taken back.
+    TakeBack {
+        player: Player,
+        column: usize,
+        row: usize,
+        time_ms: u64,
+    },
+}
+
+/// The column chosen for the human's next move with the mouse or a touchscreen. A touch that starts
+/// during a game is followed as it is dragged, and chooses the column it is released over.
+#[derive(Default, Resource)]
+struct ColumnSelection {
+    /// The column clicked or tapped this frame, if any.
+    chosen: Option<usize>,
+    /// The id of the touch being followed.
+    touch_id: Option<u64>,
+    /// The column under the touch being followed, or `None` if it is outside the board.
+    touched: Option<usize>,
+}
+
+/// Whether the threat overlay is currently shown. Toggled by pressing `THREAT_OVERLAY_KEY`.
+#[derive(Default, Resource)]
+struct ThreatOverlay {
+    visible: bool,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
 enum Player {
     Computer,
     Human,
 }
 
+impl Player {
+    /// Returns the player who moves after this one.
+    fn opponent(self) -> Player {
+        match self {
+            Player::Computer => Player::Human,
+            Player::Human => Player::Computer,
+        }
+    }
+}
+
+/// How the players' pieces are drawn. `Classic` tells them apart by red and blue alone, which some
+/// players find hard to distinguish. `ColorSafe` uses a blue and orange that remain distinct with
+/// the common forms of color blindness. `Rings` and `Symbols` give each player a differently
+/// shaped piece, so don't depend on color at all.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Resource)]
+enum PieceTheme {
+    #[default]
+    Classic,
+    ColorSafe,
+    Rings,
+    Symbols,
+}
+
+impl PieceTheme {
+    const ALL: [PieceTheme; 4] = [
+        PieceTheme::Classic,
+        PieceTheme::ColorSafe,
+        PieceTheme::Rings,
+        PieceTheme::Symbols,
+    ];
+
+    /// Returns the theme with the given name, ignoring case, or `None` if there isn't one.
+    fn from_name(name: &str) -> Option<PieceTheme> {
+        PieceTheme::ALL
+            .into_iter()
+            .find(|t| t.name().eq_ignore_ascii_case(name))
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            PieceTheme::Classic => "classic",
+            PieceTheme::ColorSafe => "color-safe",
+            PieceTheme::Rings => "rings",
+            PieceTheme::Symbols => "symbols",
+        }
+    }
+
+    /// Returns the theme after this one, wrapping around after the last.
+    fn next(self) -> PieceTheme {
+        let index = PieceTheme::ALL.iter().position(|&t| t == self).unwrap();
+        PieceTheme::ALL[(index + 1) % PieceTheme::ALL.len()]
+    }
+
+    /// Returns the sprite used to draw `player`'s pieces, i.e., the tile to use from the texture
+    /// atlas and the color to tint it.
+    fn sprite(self, player: Player) -> TextureAtlasSprite {
+        let (index, color) = match (self, player) {
+            (PieceTheme::Classic, Player::Computer) => (SOLID_PIECE_TILE, Color::RED),
+            (PieceTheme::Classic, Player::Human) => (SOLID_PIECE_TILE, Color::BLUE),
+            (PieceTheme::ColorSafe, Player::Computer) => {
+                (SOLID_PIECE_TILE, Color::rgb(0.9, 0.62, 0.0))
+            }
+            (PieceTheme::ColorSafe, Player::Human) => {
+                (SOLID_PIECE_TILE, Color::rgb(0.0, 0.45, 0.7))
+            }
+            (PieceTheme::Rings, Player::Computer) => (RING_PIECE_TILE, Color::RED),
+            (PieceTheme::Rings, Player::Human) => (SOLID_PIECE_TILE, Color::BLUE),
+            (PieceTheme::Symbols, Player::Computer) => (RING_PIECE_TILE, Color::RED),
+            (PieceTheme::Symbols, Player::Human) => (CROSS_PIECE_TILE, Color::BLUE),
+        };
+
+        TextureAtlasSprite {
+            index,
+            color,
+            ..Default::default()
+        }
+    }
+}
+
 /// Indicates if a game is in progress or is over.
 #[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, States)]
 enum GameState {
+    Editor,
     GameOver,
     #[default]
+    Menu,
     Playing,
 }
 
 /// Used when a game ends to indicate who won, or if the game was drawn.
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
 enum GameOutcome {
     ComputerWon,
     Draw,
     HumanWon,
 }
 
-/// Game data. `cells` is an array where the index of the bottom-left cell is 0, the cell to
-/// its right is 1, and the cell above is BOARD_COLUMNS. The last cell is the top-right cell, which
-/// has an index of BOARD_ROWS * BOARD_COLUMNS - 1.
+/// The ways the computer can choose its moves, from weakest to strongest.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum Strategy {
+    /// Plays in a random column.
+    #[default]
+    Random,
+    /// Completes a line if possible, otherwise blocks the opponent from completing a line,
+    /// otherwise plays randomly.
+    Greedy,
+    /// As `Greedy`, but avoids playing directly below a cell where the opponent could then
+    /// complete a line.
+    Cautious,
+}
+
+impl Strategy {
+    const ALL: [Strategy; 3] = [Strategy::Random, Strategy::Greedy, Strategy::Cautious];
+
+    /// Returns the strategy with the given name, ignoring case, or `None` if there isn't one.
+    fn from_name(name: &str) -> Option<Strategy> {
+        Strategy::ALL
+            .into_iter()
+            .find(|s| s.name().eq_ignore_ascii_case(name))
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Strategy::Random => "Random",
+            Strategy::Greedy => "Greedy",
+            Strategy::Cautious => "Cautious",
+        }
+    }
+
+    /// Returns the key of the text describing how hard this strategy is to beat, as shown in the
+    /// menu.
+    fn difficulty_key(self) -> &'static str {
+        match self {
+            Strategy::Random => "difficulty.easy",
+            Strategy::Greedy => "difficulty.medium",
+            Strategy::Cautious => "difficulty.hard",
+        }
+    }
+
+    /// Returns the next strongest strategy, wrapping around after the strongest.
+    fn next(self) -> Strategy {
+        let index = Strategy::ALL.iter().position(|&s| s == self).unwrap();
+        Strategy::ALL[(index + 1) % Strategy::ALL.len()]
+    }
+
+    /// Returns the column this strategy picks for `player` to play next in the game `gd`, using
+    /// `rng` for any random choices. The board must not be full.
+    fn choose_column(self, gd: &GameData, player: Player, rng: &mut fastrand::Rng) -> usize {
+        let columns = gd.vacant_columns();
+
+        if self != Strategy::Random {
+            if let Some(&(col, _)) = gd.threats(player).first() {
+                return col;
+            }
+            if let Some(&(col, _)) = gd.threats(player.opponent()).first() {
+                return col;
+            }
+        }
+
+        if self == Strategy::Cautious {
+            let safe_columns: Vec<usize> = columns
+                .iter()
+                .copied()
+                .filter(|&col| {
+                    let mut trial = gd.clone();
+                    trial.make_move(col, player).is_ok()
+                        && trial.threats(player.opponent()).is_empty()
+                })
+                .collect();
+
+            if !safe_columns.is_empty() {
+                return safe_columns[rng.usize(0..safe_columns.len())];
+            }
+        }
+
+        columns[rng.usize(0..columns.len())]
+    }
+}
+
+/// The progress of a match consisting of a series of games between the human and the computer.
+/// The match is won by the player who wins more of `best_of` games, and ends as soon as one player
+/// has won more games than the other could still catch up with. If neither player wins more games,
+/// e.g., because some games are drawn, the match is drawn.
+#[derive(Resource)]
+struct Match {
+    best_of: u32,
+    computer_strategy: Strategy,
+    board: BoardVariant,
+    starting_player: Player,
+    human_wins: u32,
+    computer_wins: u32,
+    draws: u32,
+}
+
+impl Match {
+    /// Creates a new match using the settings chosen in the menu.
+    fn new(choices: &MenuChoices) -> Self {
+        Self {
+            best_of: choices.best_of,
+            computer_strategy: choices.computer_strategy,
+            board: choices.board,
+            starting_player: choices.starting_player,
+            human_wins: 0,
+            computer_wins: 0,
+            draws: 0,
+        }
+    }
+
+    /// Returns the data for the next game of the match, which is drawn using `texture_atlas`.
+    fn new_game(&self, texture_atlas: Handle<TextureAtlas>) -> GameData {
+        GameData::new(
+            self.board,
+            texture_atlas,
+            self.starting_player,
+            self.computer_strategy,
+        )
+    }
+
+    /// Updates the score with the result of the game that just ended, and swaps the starting
+    /// player for the next game.
+    fn record(&mut self, outcome: GameOutcome) {
+        match outcome {
+            GameOutcome::ComputerWon => self.computer_wins += 1,
+            GameOutcome::Draw => self.draws += 1,
+            GameOutcome::HumanWon => self.human_wins += 1,
+        }
+        self.starting_player = self.starting_player.opponent();
+    }
+
+    /// Returns the result of the match if it is over, or `None` if more games need to be played.
+    fn outcome(&self) -> Option<GameOutcome> {
+        let games_played = self.human_wins + self.computer_wins + self.draws;
+        let games_left = self.best_of.saturating_sub(games_played);
+
+        if self.human_wins > self.computer_wins + games_left {
+            Some(GameOutcome::HumanWon)
+        } else if self.computer_wins > self.human_wins + games_left {
+            Some(GameOutcome::ComputerWon)
+        } else if games_left == 0 {
+            Some(GameOutcome::Draw)
+        } else {
+            None
+        }
+    }
+}
+
+/// The reasons a position set up in the editor could not have arisen in a game.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum PositionError {
+    /// The column with this index has a piece above an empty cell.
+    Floating(usize),
+    /// One player has played more than one piece more than the other.
+    PieceCounts,
+    /// A line of 4 has already been made.
+    AlreadyWon,
+    BoardFull,
+}
+
+impl PositionError {
+    /// Returns a description of this error in the language of `strings`.
+    fn message(self, strings: &StringTable) -> String {
+        match self {
+            PositionError::Floating(column) => strings.format("editor.floating", &[&(column + 1)]),
+            PositionError::PieceCounts => strings.get("editor.counts").to_string(),
+            PositionError::AlreadyWon => strings.get("editor.won").to_string(),
+            PositionError::BoardFull => strings.get("editor.full").to_string(),
+        }
+    }
+}
+
+/// A position on the standard board that the human, who moves next, can win from within `moves`
+/// of their own moves, however the computer defends.
+struct Puzzle {
+    moves: u32,
+    cells: Vec<Cell>,
+}
+
+impl Puzzle {
+    /// Returns the data for a game starting from this puzzle's position, drawn using
+    /// `texture_atlas`.
+    fn new_game(&self, texture_atlas: Handle<TextureAtlas>) -> GameData {
+        let mut gd = GameData::new(
+            BoardVariant::Standard,
+            texture_atlas,
+            Player::Human,
+            PUZZLE_STRATEGY,
+        );
+        gd.cells = self.cells.clone();
+        gd
+    }
+}
+
+/// The puzzles being played, and the index of the current one. Only present in puzzle mode.
 #[derive(Resource)]
+struct PuzzleMode {
+    puzzles: Vec<Puzzle>,
+    index: usize,
+}
+
+impl PuzzleMode {
+    fn puzzle(&self) -> &Puzzle {
+        &self.puzzles[self.index]
+    }
+}
+
+/// The sizes of board that games can be played on.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum BoardVariant {
+    Small,
+    #[default]
+    Standard,
+    Large,
+}
+
+impl BoardVariant {
+    const ALL: [BoardVariant; 3] = [
+        BoardVariant::Small,
+        BoardVariant::Standard,
+        BoardVariant::Large,
+    ];
+
+    /// Returns the key of the text naming this board size, as shown in the menu.
+    fn name_key(self) -> &'static str {
+        match self {
+            BoardVariant::Small => "board.small",
+            BoardVariant::Standard => "board.standard",
+            BoardVariant::Large => "board.large",
+        }
+    }
+
+    /// Returns the next larger board size, wrapping around after the largest.
+    fn next(self) -> BoardVariant {
+        let index = BoardVariant::ALL.iter().position(|&b| b == self).unwrap();
+        BoardVariant::ALL[(index + 1) % BoardVariant::ALL.len()]
+    }
+
+    fn columns(self) -> usize {
+        match self {
+            BoardVariant::Small => 6,
+            BoardVariant::Standard => 7,
+            BoardVariant::Large => 8,
+        }
+    }
+
+    fn rows(self) -> usize {
+        match self {
+            BoardVariant::Small => 5,
+            BoardVariant::Standard => 6,
+            BoardVariant::Large => 7,
+        }
+    }
+}
+
+/// Game data. `cells` holds `columns * rows` cells, where the index of the bottom-left cell is 0,
+/// the cell to its right is 1, and the cell above is `columns`. The last cell is the top-right
+/// cell, which has an index of `rows * columns - 1`. `moves` holds the column and row of each move
+/// played so far, in order.
+#[derive(Clone, Resource)]
 struct GameData {
-    cells: [Cell; BOARD_COLUMNS * BOARD_ROWS],
+    columns: usize,
+    rows: usize,
+    cells: Vec<Cell>,
+    moves: Vec<(usize, usize)>,
     texture_atlas: Handle<TextureAtlas>,
     current_player: Option<Player>,
+    computer_strategy: Strategy,
     game_outcome: Option<GameOutcome>,
 }
 
 impl GameData {
-    /// Creates a new game consisting of an empty board, the given texture atlas to use to draw
-    /// the board and player pieces, and which player has the first turn.
-    fn new(texture_atlas: Handle<TextureAtlas>, starting_player: Player) -> Self {
+    /// Creates a new game consisting of an empty board of the size given by `board`, the given
+    /// texture atlas to use to draw the board and player pieces, which player has the first turn,
+    /// and how the computer chooses its moves.
+    fn new(
+        board: BoardVariant,
+        texture_atlas: Handle<TextureAtlas>,
+        starting_player: Player,
+        computer_strategy: Strategy,
+    ) -> Self {
         Self {
-            cells: [None; BOARD_COLUMNS * BOARD_ROWS],
+            columns: board.columns(),
+            rows: board.rows(),
+            cells: vec![None; board.columns() * board.rows()],
+            moves: Vec::new(),
             texture_atlas,
             current_player: Some(starting_player),
+            computer_strategy,
             game_outcome: None,
         }
     }
@@ -67,18 +753,27 @@ impl GameData {
     /// the row index of the new piece with an `Ok`, or `Err` if `col` is full.
     fn make_move(&mut self, column: usize, player: Player) -> Result<usize, ()> {
         if let Some(vacant_row) = self.lowest_vacant_row(column) {
-            self.cells[BOARD_COLUMNS * vacant_row + column] = Some(player);
+            self.cells[self.columns * vacant_row + column] = Some(player);
+            self.moves.push((column, vacant_row));
             Result::Ok(vacant_row)
         } else {
             Result::Err(())
         }
     }
 
+    /// Removes the most recent move from the board. Returns the column and row it was played in,
+    /// and the player who made it, or `None` if no moves have been made.
+    fn take_back_move(&mut self) -> Option<(usize, usize, Player)> {
+        let (column, row) = self.moves.pop()?;
+        let player = self.cells[row * self.columns + column].take()?;
+        Some((column, row, player))
+    }
+
     /// Returns the index of the row nearest the bottom of the game board that has a vacant cell in
     /// the given `col`. Returns `None` if the column is full.
     fn lowest_vacant_row(&self, col: usize) -> Option<usize> {
-        for row in 0..BOARD_ROWS {
-            if self.cells[row * BOARD_COLUMNS + col] == None {
+        for row in 0..self.rows {
+            if self.cells[row * self.columns + col] == None {
                 return Some(row);
             }
         }
@@ -96,7 +791,7 @@ impl GameData {
     // are discarded before being used to perform lookups. They are signed so that they can extend
     // in the negative direction.
     fn is_winning_move(&self, col: usize, row: usize) -> bool {
-        let played_piece = self.cells[row * BOARD_COLUMNS + col];
+        let played_piece = self.cells[row * self.columns + col];
         if played_piece == None {
             return false;
         }
@@ -108,11 +803,11 @@ impl GameData {
                 let c = col as i8 + i * c_disp;
                 let r = row as i8 + i * r_disp;
 
-                if (c < 0) || (c >= 7) || (r < 0) || (r >= 6) {
+                if (c < 0) || (c >= self.columns as i8) || (r < 0) || (r >= self.rows as i8) {
                     continue;
                 }
 
-                if self.cells[(r * BOARD_COLUMNS as i8 + c) as usize] == played_piece {
+                if self.cells[(r * self.columns as i8 + c) as usize] == played_piece {
                     line_length += 1;
                     if line_length == 4 {
                         return true;
@@ -125,19 +820,135 @@ impl GameData {
         false
     }
 
+    /// Returns the column and row of every cell where `player` would complete a line if they played
+    /// in that column on their next move.
+    fn threats(&self, player: Player) -> Vec<(usize, usize)> {
+        let mut trial = self.clone();
+        let mut result = Vec::new();
+
+        for col in 0..self.columns {
+            if let Ok(row) = trial.make_move(col, player) {
+                if trial.is_winning_move(col, row) {
+                    result.push((col, row));
+                }
+                trial.take_back_move();
+            }
+        }
+        result
+    }
+
+    /// Returns the index of every column that has room for another piece.
+    fn vacant_columns(&self) -> Vec<usize> {
+        (0..self.columns)
+            .filter(|&col| self.lowest_vacant_row(col).is_some())
+            .collect()
+    }
+
     /// Returns `true` if every cell in the top row is full, i.e., no further moves are possible.
     fn is_board_full(&self) -> bool {
-        for col in 0..BOARD_COLUMNS {
-            if self.cells[(BOARD_ROWS - 1) * BOARD_COLUMNS + col] == None {
+        for col in 0..self.columns {
+            if self.cells[(self.rows - 1) * self.columns + col] == None {
                 return false;
             }
         }
         true
     }
+
+    /// Returns the pieces on the board as one string per row from the top down, using X for the
+    /// human's pieces, O for the computer's and . for empty cells, as in the puzzle file.
+    fn position_rows(&self) -> Vec<String> {
+        (0..self.rows)
+            .rev()
+            .map(|row| {
+                self.cells[row * self.columns..(row + 1) * self.columns]
+                    .iter()
+                    .map(|cell| match cell {
+                        Some(Player::Human) => 'X',
+                        Some(Player::Computer) => 'O',
+                        None => '.',
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Checks that the pieces on the board could have been reached in a game that is still in
+    /// progress. If so, returns the player whose turn it is, which is `preferred` if both players
+    /// have played the same number of pieces.
+    fn validate_position(&self, preferred: Player) -> Result<Player, PositionError> {
+        for col in 0..self.columns {
+            if let Some(vacant_row) = self.lowest_vacant_row(col) {
+                let floating = (vacant_row..self.rows)
+                    .any(|row| self.cells[row * self.columns + col].is_some());
+                if floating {
+                    return Err(PositionError::Floating(col));
+                }
+            }
+        }
+
+        let count = |player| self.cells.iter().filter(|&&c| c == Some(player)).count();
+        let to_move = match count(Player::Human) as isize - count(Player::Computer) as isize {
+            0 => preferred,
+            1 => Player::Computer,
+            -1 => Player::Human,
+            _ => return Err(PositionError::PieceCounts),
+        };
+
+        if (0..self.rows).any(|row| (0..self.columns).any(|col| self.is_winning_move(col, row))) {
+            return Err(PositionError::AlreadyWon);
+        }
+        if self.is_board_full() {
+            return Err(PositionError::BoardFull);
+        }
+        Ok(to_move)
+    }
+
+    /// Returns `true` if `player`, whose turn it is, can complete a line within `moves` of their
+    /// own moves, however their opponent replies.
+    fn can_force_win(&mut self, player: Player, moves: u32) -> bool {
+        if moves == 0 {
+            return false;
+        }
+
+        for col in 0..self.columns {
+            if let Ok(row) = self.make_move(col, player) {
+                let wins = self.is_winning_move(col, row)
+                    || (moves > 1 && !self.opponent_can_escape(player, moves - 1));
+                self.take_back_move();
+
+                if wins {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Returns `true` if the opponent of `player`, whose turn it is, has a move after which
+    /// `player` cannot force a win within `moves` of their own moves.
+    fn opponent_can_escape(&mut self, player: Player, moves: u32) -> bool {
+        let opponent = player.opponent();
+        let mut can_move = false;
+
+        for col in 0..self.columns {
+            if let Ok(row) = self.make_move(col, opponent) {
+                can_move = true;
+                let escapes = self.is_winning_move(col, row) || !self.can_force_win(player, moves);
+                self.take_back_move();
+
+                if escapes {
+                    return true;
+                }
+            }
+        }
+
+        // A full board is a draw, so the opponent has escaped.
+        !can_move
+    }
 }
 
 /// Creates a 2D camera and loads a texture atlas file that contains a tile used for each cell of
-/// the board, and a tile for a player piece.
+/// the board, and the tiles for player pieces.
 fn setup(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
@@ -153,161 +964,545 @@ fn setup(
     let texture_atlas = TextureAtlas::from_grid(
         texture_handle,
         Vec2::new(SPRITE_WIDTH as f32, SPRITE_HEIGHT as f32),
-        2, // Number of columns of individual tiles in our image file loaded from disk.
-        1, // Number of rows of individual tiles in our image file loaded from disk.
+        4,    // Number of columns of individual tiles in our image file loaded from disk.
+        1,    // Number of rows of individual tiles in our image file loaded from disk.
         None, // There is no padding between tiles.
         None, // The first tile is not offset from the top-left corner.
     );
-    let texture_atlas_handle: Handle<_> = texture_atlases.add(texture_atlas);
+    commands.insert_resource(SpriteAtlas(texture_atlases.add(texture_atlas)));
+}
 
-    commands.insert_resource(GameData::new(texture_atlas_handle.clone(), Player::Human));
+/// Starts a match using the settings chosen in the menu, creating the board for its first game.
+fn start_match(
+    mut commands: Commands,
+    atlas: Res<SpriteAtlas>,
+    choices: Res<MenuChoices>,
+    puzzle_mode: Option<Res<PuzzleMode>>,
+) {
+    let mut game_match = Match::new(&choices);
+    let gd = match puzzle_mode {
+        Some(puzzle_mode) => {
+            game_match.best_of = 1;
+            game_match.board = BoardVariant::Standard;
+            game_match.starting_player = Player::Human;
+            game_match.computer_strategy = PUZZLE_STRATEGY;
+            puzzle_mode.puzzle().new_game(atlas.0.clone())
+        }
+        None => game_match.new_game(atlas.0.clone()),
+    };
 
     create_board(
         &mut commands,
-        texture_atlas_handle.clone(),
+        &gd,
         TextureAtlasSprite {
             index: 0,
             ..Default::default()
         },
     );
-}
-
-/// Creates the graphics for an empty board using the graphics tile passed.
-fn create_board(
-    commands: &mut Commands,
-    texture_atlas_handle: Handle<TextureAtlas>,
-    board_sprite: TextureAtlasSprite,
-) {
-    let x_left = ((BOARD_COLUMNS - 1) * SPRITE_WIDTH) as f32 / -2.0;
-    let y_bottom = ((BOARD_ROWS - 1) * SPRITE_HEIGHT) as f32 / -2.0;
-
-    for r in 0..BOARD_ROWS {
-        for c in 0..BOARD_COLUMNS {
-            let position = Vec3::new(
-                x_left + (SPRITE_WIDTH * c) as f32,
-                y_bottom + (SPRITE_HEIGHT * r) as f32,
-                1.0,
-            );
 
-            commands.spawn(SpriteSheetBundle {
-                sprite: board_sprite.clone(),
-                texture_atlas: texture_atlas_handle.clone(),
-                transform: Transform::from_translation(position),
-                ..Default::default()
-            });
-        }
-    }
+    commands.insert_resource(gd);
+    commands.insert_resource(game_match);
 }
 
-/// The main game loop that is called each frame to make a move on behalf of the human player or
-/// computer, and then check to see if that move ends the game.
-fn game_loop(
-    mut mouse_button_input: ResMut<Input<MouseButton>>,
-    mut primary_query: Query<&mut Window, With<PrimaryWindow>>,
-    camera: Query<&Transform, With<PrimaryCamera>>,
-    mut commands: Commands,
-    mut gd: ResMut<GameData>,
-    mut next_state: ResMut<NextState<GameState>>,
-) {
-    match gd.current_player {
-        Some(Player::Human) => {
-            let mut primary_window = primary_query
-                .get_single_mut()
-                .expect("Internal error: cannot locate primary window");
-
-            let result = human_move(
-                &mut mouse_button_input,
-                &mut primary_window,
-                &camera,
-                &mut commands,
-                &mut gd,
-            );
+/// Creates the graphics for the empty board of the game in `gd` using the graphics tile passed.
+fn create_board(commands: &mut Commands, gd: &GameData, board_sprite: TextureAtlasSprite) {
+    for r in 0..gd.rows {
+        for c in 0..gd.columns {
+            let position = cell_position(gd, c, r).extend(1.0);
 
-            if result == true {
-                gd.current_player = Some(Player::Computer);
-            }
-        }
-        Some(Player::Computer) => {
-            computer_move(&mut commands, &mut gd);
-            gd.current_player = Some(Player::Human);
+            commands
+                .spawn(SpriteSheetBundle {
+                    sprite: board_sprite.clone(),
+                    texture_atlas: gd.texture_atlas.clone(),
+                    transform: Transform::from_translation(position),
+                    ..Default::default()
+                })
+                .insert(BoardTile);
         }
-        None => {}
     }
+}
 
-    let game_status = is_game_over(&gd);
-    if game_status.is_some() {
-        gd.current_player = None;
-        gd.game_outcome = game_status;
-        next_state.set(GameState::GameOver);
+/// Removes the board and the match that was played on it.
+fn clear_board(mut commands: Commands, tiles: Query<Entity, With<BoardTile>>) {
+    for entity in tiles.iter() {
+        commands.entity(entity).despawn();
     }
+    commands.remove_resource::<GameData>();
+    commands.remove_resource::<Match>();
+    commands.remove_resource::<PuzzleMode>();
 }
 
-/// If the user clicked on a column of the board, attempts to play a piece there. Returns `true` if
-/// a move was successfully made, `false` otherwise.
-fn human_move(
-    mouse_button_input: &mut ResMut<Input<MouseButton>>,
-    primary_window: &mut Window,
-    camera: &Query<&Transform, With<PrimaryCamera>>,
-    commands: &mut Commands,
-    gd: &mut ResMut<GameData>,
-) -> bool {
-    let mut result = false;
+/// Adds a piece to the graphical game board for every piece already in the game in `gd`, such as
+/// those of a position set up in the editor or a puzzle.
+fn spawn_pieces(mut commands: Commands, gd: Res<GameData>, theme: Res<PieceTheme>) {
+    add_position_to_board(&gd, &mut commands, *theme);
+}
 
-    if mouse_button_input.just_pressed(MouseButton::Left) {
-        if let Some(pos) = primary_window.cursor_position() {
-            if let Ok(selected_column) =
-                convert_mouse_position_to_column_id(&primary_window, &camera.single(), pos)
-            {
-                if let Result::Ok(r) = gd.make_move(selected_column, Player::Human) {
-                    add_piece_to_board(&gd, commands, selected_column, r, Player::Human);
-                    result = true;
-                }
+/// Displays the menu, with a button for each setting and a button to start playing.
+fn spawn_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    choices: Res<MenuChoices>,
+    theme: Res<PieceTheme>,
+    strings: Res<StringTable>,
+) {
+    let font = asset_server.load(FONT_ASSET_FILENAME);
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                row_gap: Val::Px(12.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(MenuRoot)
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle::from_section(
+                    strings.get("menu.title"),
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 80.0,
+                        color: TEXT_COLOR,
+                    },
+                ))
+                .insert(MenuTitle);
+
+            for button in MenuButton::ALL {
+                parent
+                    .spawn(ButtonBundle {
+                        style: Style {
+                            width: Val::Px(420.0),
+                            height: Val::Px(50.0),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        background_color: BUTTON_COLOR.into(),
+                        ..Default::default()
+                    })
+                    .insert(button)
+                    .with_children(|parent| {
+                        parent
+                            .spawn(TextBundle::from_section(
+                                button.label(&choices, *theme, &strings),
+                                TextStyle {
+                                    font: font.clone(),
+                                    font_size: 30.0,
+                                    color: TEXT_COLOR,
+                                },
+                            ))
+                            .insert(MenuLabel(button));
+                    });
             }
+        });
+}
+
+/// Removes the menu.
+fn despawn_menu(mut commands: Commands, menu: Query<Entity, With<MenuRoot>>) {
+    for entity in menu.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Highlights the menu button under the mouse pointer, and responds to clicks. Clicking a setting
+/// changes it to its next value, clicking `Editor` opens the position editor, and clicking
+/// `Puzzles` or `Play` starts a match.
+fn menu_interaction(
+    mut commands: Commands,
+    mut buttons: Query<(&Interaction, &MenuButton, &mut BackgroundColor), Changed<Interaction>>,
+    mut choices: ResMut<MenuChoices>,
+    mut theme: ResMut<PieceTheme>,
+    mut strings: ResMut<StringTable>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for (interaction, button, mut color) in buttons.iter_mut() {
+        match interaction {
+            Interaction::Pressed => match button {
+                MenuButton::Mode => {
+                    let index = MATCH_LENGTHS.iter().position(|&n| n == choices.best_of);
+                    choices.best_of = match index {
+                        Some(i) => MATCH_LENGTHS[(i + 1) % MATCH_LENGTHS.len()],
+                        None => MATCH_LENGTHS[0],
+                    };
+                }
+                MenuButton::Difficulty => {
+                    choices.computer_strategy = choices.computer_strategy.next();
+                }
+                MenuButton::Starter => {
+                    choices.starting_player = choices.starting_player.opponent();
+                }
+                MenuButton::Board => choices.board = choices.board.next(),
+                MenuButton::Theme => select_next_theme(&mut theme),
+                MenuButton::Language => select_next_language(&mut strings),
+                MenuButton::Editor => next_state.set(GameState::Editor),
+                MenuButton::Puzzles => {
+                    let puzzles = load_puzzles();
+                    if puzzles.is_empty() {
+                        warn!("There are no puzzles to play");
+                    } else {
+                        commands.insert_resource(PuzzleMode { puzzles, index: 0 });
+                        next_state.set(GameState::Playing);
+                    }
+                }
+                MenuButton::Play => next_state.set(GameState::Playing),
+            },
+            Interaction::Hovered => *color = HOVERED_BUTTON_COLOR.into(),
+            Interaction::None => *color = BUTTON_COLOR.into(),
+        }
+    }
+}
+
+/// Updates the text of the menu when a setting or the language changes.
+fn update_menu_text(
+    mut labels: Query<(&MenuLabel, &mut Text), Without<MenuTitle>>,
+    mut title: Query<&mut Text, With<MenuTitle>>,
+    choices: Res<MenuChoices>,
+    theme: Res<PieceTheme>,
+    strings: Res<StringTable>,
+) {
+    if !choices.is_changed() && !theme.is_changed() && !strings.is_changed() {
+        return;
+    }
+
+    for (label, mut text) in labels.iter_mut() {
+        text.sections[0].value = label.0.label(&choices, *theme, &strings);
+    }
+    for mut text in title.iter_mut() {
+        text.sections[0].value = strings.get("menu.title").to_string();
+    }
+}
+
+/// Switches to the language after the one in use, in alphabetical order of their codes, and saves
+/// it as the language to use in future runs. Does nothing if there are no language files.
+fn select_next_language(strings: &mut StringTable) {
+    let languages = strings.available_languages();
+    if languages.is_empty() {
+        return;
+    }
+    let index = languages.iter().position(|l| l == strings.language());
+    let language = match index {
+        Some(i) => &languages[(i + 1) % languages.len()],
+        None => &languages[0],
+    };
+
+    *strings = StringTable::load(LOCALE_NAME, language);
+    settings::save_setting(SETTINGS_FILENAME, LANGUAGE_SETTING, strings.language());
+}
+
+/// Shows the position editor's instructions, and stops either player from moving until the
+/// position is played.
+fn start_editor(
+    mut commands: Commands,
+    mut gd: ResMut<GameData>,
+    asset_server: Res<AssetServer>,
+    strings: Res<StringTable>,
+) {
+    gd.current_player = None;
+
+    let help = display_text(
+        &mut commands,
+        &asset_server,
+        strings.get("editor.help"),
+        30.0,
+        true,
+    );
+    commands.entity(help).insert(EditorText);
+}
+
+/// When a cell is clicked or tapped in the position editor, changes it from empty to holding the
+/// human's piece, then the computer's piece, then back to empty.
+fn edit_position(
+    mut mouse_button_input: ResMut<Input<MouseButton>>,
+    touches: Res<Touches>,
+    primary_query: Query<&Window, With<PrimaryWindow>>,
+    camera: Query<&Transform, With<PrimaryCamera>>,
+    mut gd: ResMut<GameData>,
+) {
+    let primary_window = primary_query
+        .get_single()
+        .expect("Internal error: cannot locate primary window");
+
+    let mut positions: Vec<Vec2> = touches.iter_just_pressed().map(|t| t.position()).collect();
+    if mouse_button_input.just_pressed(MouseButton::Left) {
+        positions.extend(primary_window.cursor_position());
+        mouse_button_input.reset(MouseButton::Left);
+    }
+
+    for pos in positions {
+        let cell = convert_window_position_to_cell(primary_window, camera.single(), pos, &gd);
+        if let Some((column, row)) = cell {
+            let index = row * gd.columns + column;
+            gd.cells[index] = match gd.cells[index] {
+                None => Some(Player::Human),
+                Some(Player::Human) => Some(Player::Computer),
+                Some(Player::Computer) => None,
+            };
+        }
+    }
+}
+
+/// Redraws the pieces in the position editor, and says whether the position can be played, when
+/// the position changes.
+fn update_editor(
+    mut commands: Commands,
+    gd: Res<GameData>,
+    choices: Res<MenuChoices>,
+    theme: Res<PieceTheme>,
+    asset_server: Res<AssetServer>,
+    strings: Res<StringTable>,
+    old: Query<Entity, EditorRedrawFilter>,
+) {
+    if !gd.is_changed() {
+        return;
+    }
+
+    for entity in old.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    add_position_to_board(&gd, &mut commands, *theme);
+
+    let status = match gd.validate_position(choices.starting_player) {
+        Ok(_) => strings.get("editor.ready").to_string(),
+        Err(e) => e.message(&strings),
+    };
+    let text = display_text(&mut commands, &asset_server, &status, 30.0, false);
+    commands.entity(text).insert((EditorText, EditorStatus));
+}
+
+/// Plays a game from the position in the editor when `EDITOR_PLAY_KEY` is pressed, if the position
+/// could have arisen in a game. Returns to the menu when `MENU_KEY` is pressed.
+fn leave_editor(
+    key: Res<Input<KeyCode>>,
+    mut gd: ResMut<GameData>,
+    mut game_match: ResMut<Match>,
+    choices: Res<MenuChoices>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if key.just_pressed(MENU_KEY) {
+        next_state.set(GameState::Menu);
+    } else if key.just_pressed(EDITOR_PLAY_KEY) {
+        if let Ok(to_move) = gd.validate_position(choices.starting_player) {
+            gd.current_player = Some(to_move);
+            game_match.best_of = 1;
+            game_match.starting_player = to_move;
+            next_state.set(GameState::Playing);
         }
+    }
+}
+
+/// Removes the position editor's messages and pieces. The pieces are redrawn by `spawn_pieces` if
+/// the position is played.
+fn despawn_editor(
+    mut commands: Commands,
+    pieces: Query<Entity, With<Piece>>,
+    texts: Query<Entity, With<EditorText>>,
+) {
+    for entity in pieces.iter().chain(texts.iter()) {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Updates `selection` with the column the user clicked or finished touching this frame, and the
+/// column currently being touched.
+fn select_column(
+    mut mouse_button_input: ResMut<Input<MouseButton>>,
+    touches: Res<Touches>,
+    primary_query: Query<&Window, With<PrimaryWindow>>,
+    camera: Query<&Transform, With<PrimaryCamera>>,
+    gd: Res<GameData>,
+    mut selection: ResMut<ColumnSelection>,
+) {
+    let primary_window = primary_query
+        .get_single()
+        .expect("Internal error: cannot locate primary window");
+    let to_column = |pos| {
+        convert_window_position_to_column_id(primary_window, camera.single(), pos, gd.columns).ok()
+    };
+
+    selection.chosen = None;
+
+    if mouse_button_input.just_pressed(MouseButton::Left) {
+        selection.chosen = primary_window.cursor_position().and_then(to_column);
         mouse_button_input.reset(MouseButton::Left);
     }
 
+    if selection.touch_id.is_none() {
+        selection.touch_id = touches.iter_just_pressed().next().map(|touch| touch.id());
+    }
+
+    let Some(id) = selection.touch_id else {
+        return;
+    };
+
+    if let Some(touch) = touches.get_pressed(id) {
+        selection.touched = to_column(touch.position());
+    } else {
+        if let Some(touch) = touches.get_released(id) {
+            selection.chosen = to_column(touch.position());
+        }
+        selection.touch_id = None;
+        selection.touched = None;
+    }
+}
+
+/// Shows a faded piece in the cell where the human's piece would land in the column being touched,
+/// if it is the human's turn.
+fn show_touch_preview(
+    mut commands: Commands,
+    selection: Res<ColumnSelection>,
+    gd: Res<GameData>,
+    theme: Res<PieceTheme>,
+    mut previews: Query<(Entity, &mut Transform), With<TouchPreview>>,
+) {
+    let cell = selection
+        .touched
+        .filter(|_| gd.current_player == Some(Player::Human))
+        .and_then(|column| Some((column, gd.lowest_vacant_row(column)?)));
+
+    let Some((column, row)) = cell else {
+        for (entity, _) in previews.iter() {
+            commands.entity(entity).despawn();
+        }
+        return;
+    };
+
+    let translation = cell_position(&gd, column, row).extend(0.0);
+    if let Ok((_, mut transform)) = previews.get_single_mut() {
+        transform.translation = translation;
+        return;
+    }
+
+    let mut sprite = theme.sprite(Player::Human);
+    sprite.color.set_a(0.5);
+    commands
+        .spawn(SpriteSheetBundle {
+            sprite,
+            texture_atlas: gd.texture_atlas.clone(),
+            transform: Transform::from_translation(translation),
+            ..Default::default()
+        })
+        .insert(TouchPreview);
+}
+
+/// The main game loop that is called each frame to make a move on behalf of the human player or
+/// computer, and then check to see if that move ends the game.
+fn game_loop(
+    selection: Res<ColumnSelection>,
+    mut commands: Commands,
+    mut gd: ResMut<GameData>,
+    theme: Res<PieceTheme>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    match gd.current_player {
+        Some(Player::Human) => {
+            let result = human_move(selection.chosen, &mut commands, &mut gd, *theme);
+
+            if result == true {
+                end_turn(&mut gd, &mut next_state, Player::Computer);
+            }
+        }
+        // The computer's moves are made by `computer_turn`.
+        Some(Player::Computer) => {}
+        None => {}
+    }
+}
+
+/// Passes the turn to `next_player`, unless the move just made ended the game, in which case the
+/// outcome is recorded in `gd` and the game state is changed to `GameOver`.
+fn end_turn(gd: &mut GameData, next_state: &mut NextState<GameState>, next_player: Player) {
+    let game_status = is_game_over(gd);
+    if game_status.is_some() {
+        gd.current_player = None;
+        gd.game_outcome = game_status;
+        next_state.set(GameState::GameOver);
+    } else {
+        gd.current_player = Some(next_player);
+    }
+}
+
+/// If the user clicked or tapped a column of the board, attempts to play a piece there. Returns
+/// `true` if a move was successfully made, `false` otherwise.
+fn human_move(
+    selected_column: Option<usize>,
+    commands: &mut Commands,
+    gd: &mut ResMut<GameData>,
+    theme: PieceTheme,
+) -> bool {
+    let mut result = false;
+
+    if let Some(selected_column) = selected_column {
+        if let Result::Ok(r) = gd.make_move(selected_column, Player::Human) {
+            add_piece_to_board(&gd, commands, selected_column, r, Player::Human, theme);
+            result = true;
+        }
+    }
+
     result
 }
 
-/// Adds a piece to the graphical game board at coordinates `col` and `row`, and using the color
-/// of the current player, as defined in `gd`.
+/// Adds a piece to the graphical game board at coordinates `col` and `row`, drawn as `theme`
+/// specifies for `player`.
 fn add_piece_to_board(
     gd: &GameData,
     commands: &mut Commands,
     column: usize,
     row: usize,
     player: Player,
+    theme: PieceTheme,
 ) {
-    let x_offset = (BOARD_COLUMNS - 1) as f32 / 2.0;
-    let y_offset = (BOARD_ROWS - 1) as f32 / 2.0;
-
-    let x = (column as f32 - x_offset) * SPRITE_WIDTH as f32;
-    let y = (row as f32 - y_offset) * SPRITE_HEIGHT as f32;
+    commands
+        .spawn(SpriteSheetBundle {
+            sprite: theme.sprite(player),
+            texture_atlas: gd.texture_atlas.clone(),
+            transform: Transform::from_translation(cell_position(gd, column, row).extend(0.0)),
+            ..Default::default()
+        })
+        .insert(Piece {
+            column,
+            row,
+            player,
+        });
+}
 
-    let mut color = Color::BLUE;
-    if player == Player::Computer {
-        color = Color::RED;
+/// Adds a piece to the graphical game board for every piece in the game in `gd`, drawn as `theme`
+/// specifies.
+fn add_position_to_board(gd: &GameData, commands: &mut Commands, theme: PieceTheme) {
+    for row in 0..gd.rows {
+        for column in 0..gd.columns {
+            if let Some(player) = gd.cells[row * gd.columns + column] {
+                add_piece_to_board(gd, commands, column, row, player, theme);
+            }
+        }
     }
+}
 
-    commands.spawn(SpriteSheetBundle {
-        sprite: TextureAtlasSprite {
-            index: 1,
-            color,
-            ..Default::default()
-        },
-        texture_atlas: gd.texture_atlas.clone(),
-        transform: Transform::from_translation(Vec3::new(x, y, 0.0)),
-        ..Default::default()
-    });
+/// Returns the world position of the center of the cell at `column` and `row` on the board of the
+/// game in `gd`.
+fn cell_position(gd: &GameData, column: usize, row: usize) -> Vec2 {
+    let x_offset = (gd.columns - 1) as f32 / 2.0;
+    let y_offset = (gd.rows - 1) as f32 / 2.0;
+
+    Vec2::new(
+        (column as f32 - x_offset) * SPRITE_WIDTH as f32,
+        (row as f32 - y_offset) * SPRITE_HEIGHT as f32,
+    )
 }
 
-/// Converts the raw window position in `pos` to a column id, where the leftmost column is 0. If
-/// `pos` is horizontally outside the board, return `Err`.
-fn convert_mouse_position_to_column_id(
+/// Converts the raw window position in `pos` of the mouse pointer or a touch to a column id, where
+/// the leftmost column is 0, on a board with `columns` columns. If `pos` is horizontally outside
+/// the board, return `Err`.
+fn convert_window_position_to_column_id(
     window: &Window,
     camera_transform: &Transform,
     pos: Vec2,
+    columns: usize,
 ) -> Result<usize, ()> {
     let window_size = Vec2::new(window.width() as f32, window.height() as f32);
 
@@ -318,38 +1513,177 @@ fn convert_mouse_position_to_column_id(
     let pos_world = camera_transform.compute_matrix() * p.extend(0.0).extend(1.0);
 
     // Convert this 'world' position to the corresponding board column, where 0 is on the left.
-    let pos_distance_x = pos_world.x + (BOARD_COLUMNS as f32 / 2.0) * SPRITE_WIDTH as f32;
+    let pos_distance_x = pos_world.x + (columns as f32 / 2.0) * SPRITE_WIDTH as f32;
     let pos_col = (pos_distance_x / SPRITE_WIDTH as f32) as usize;
 
-    if (pos_distance_x > 0.0) & (pos_col < BOARD_COLUMNS) {
+    if (pos_distance_x > 0.0) & (pos_col < columns) {
         return Ok(pos_col);
     }
     Err(())
 }
 
-/// Makes a move in a random column.
+/// Converts the raw window position in `pos` of the mouse pointer or a touch to the column and row
+/// of the cell of the board in `gd` at that position. Returns `None` if `pos` is outside the board.
+fn convert_window_position_to_cell(
+    window: &Window,
+    camera_transform: &Transform,
+    pos: Vec2,
+    gd: &GameData,
+) -> Option<(usize, usize)> {
+    let column =
+        convert_window_position_to_column_id(window, camera_transform, pos, gd.columns).ok()?;
+
+    // Window positions increase downwards from the top of the window, whereas world positions
+    // increase upwards from its center.
+    let y = window.height() / 2.0 - pos.y;
+    let pos_world = camera_transform.compute_matrix() * Vec4::new(0.0, y, 0.0, 1.0);
+
+    let pos_distance_y = pos_world.y + (gd.rows as f32 / 2.0) * SPRITE_HEIGHT as f32;
+    let row = (pos_distance_y / SPRITE_HEIGHT as f32) as usize;
+
+    if pos_distance_y > 0.0 && row < gd.rows {
+        Some((column, row))
+    } else {
+        None
+    }
+}
+
+/// Displays a message if the computer's move is still being chosen a frame after it was started.
+fn show_thinking_indicator(
+    mut commands: Commands,
+    thinking: Option<ResMut<ComputerThinking>>,
+    asset_server: Res<AssetServer>,
+    strings: Res<StringTable>,
+) {
+    let Some(mut thinking) = thinking else {
+        return;
+    };
+
+    if thinking.indicator.is_none() && !thinking.task.is_finished() {
+        let indicator = display_text(
+            &mut commands,
+            &asset_server,
+            strings.get("game.thinking"),
+            30.0,
+            false,
+        );
+        thinking.indicator = Some(indicator);
+    }
+}
+
+/// When it is the computer's turn, starts a task on the `AsyncComputeTaskPool` that chooses its
+/// move, so that a slow strategy doesn't stall rendering. The task is polled each frame, and the
+/// move is made once it finishes.
+fn computer_turn(
+    mut commands: Commands,
+    mut gd: ResMut<GameData>,
+    mut rng: ResMut<SeededRng>,
+    thinking: Option<ResMut<ComputerThinking>>,
+    theme: Res<PieceTheme>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if gd.current_player != Some(Player::Computer) {
+        return;
+    }
+
+    let Some(mut thinking) = thinking else {
+        let trial = gd.clone();
+        let mut task_rng = rng.fork();
+        let task = AsyncComputeTaskPool::get().spawn(async move {
+            trial
+                .computer_strategy
+                .choose_column(&trial, Player::Computer, &mut task_rng)
+        });
+
+        commands.insert_resource(ComputerThinking {
+            task,
+            indicator: None,
+        });
+        return;
+    };
+
+    if !thinking.task.is_finished() {
+        return;
+    }
+
+    let selected_column = block_on(&mut thinking.task);
+    cancel_computer_move(&mut commands, &thinking);
+    computer_move(&mut commands, &mut gd, selected_column, *theme);
+    end_turn(&mut gd, &mut next_state, Player::Human);
+}
+
+/// Plays the computer's piece in `column`.
 fn computer_move(
     commands: &mut Commands,
     gd: &mut ResMut<GameData>,
-    // mut next_state: ResMut<NextState<GameState>>,
+    column: usize,
+    theme: PieceTheme,
 ) {
-    loop {
-        let selected_column = fastrand::u8(0..BOARD_COLUMNS as u8) as usize;
+    if let Result::Ok(r) = gd.make_move(column, Player::Computer) {
+        add_piece_to_board(gd, commands, column, r, Player::Computer, theme);
+    }
+}
 
-        if let Result::Ok(r) = gd.make_move(selected_column, Player::Computer) {
-            add_piece_to_board(&gd, commands, selected_column, r, Player::Computer);
+/// Removes `thinking` and its message, dropping its task. If the task is still running, this
+/// cancels it.
+fn cancel_computer_move(commands: &mut Commands, thinking: &ComputerThinking) {
+    if let Some(indicator) = thinking.indicator {
+        commands.entity(indicator).despawn_recursive();
+    }
+    commands.remove_resource::<ComputerThinking>();
+}
+
+/// When `UNDO_KEY` is pressed, takes back the human's last move, along with the computer's reply
+/// if it has made one. When `RESTART_KEY` is pressed, takes back every move so the game starts
+/// again. Either cancels the computer's move if it is still being chosen.
+fn undo_or_restart(
+    mut commands: Commands,
+    key: Res<Input<KeyCode>>,
+    mut gd: ResMut<GameData>,
+    thinking: Option<Res<ComputerThinking>>,
+    pieces: Query<(Entity, &Piece)>,
+) {
+    let restart = key.just_pressed(RESTART_KEY);
+    if !restart && !key.just_pressed(UNDO_KEY) {
+        return;
+    }
+
+    let human_has_moved = gd
+        .moves
+        .iter()
+        .any(|&(col, row)| gd.cells[row * gd.columns + col] == Some(Player::Human));
+    if gd.current_player.is_none() || (!restart && !human_has_moved) {
+        return;
+    }
+
+    if let Some(thinking) = thinking {
+        cancel_computer_move(&mut commands, &thinking);
+    }
+
+    let mut taken_back = Vec::new();
+    while let Some((column, row, player)) = gd.take_back_move() {
+        taken_back.push((column, row));
+        gd.current_player = Some(player);
+
+        if player == Player::Human && !restart {
             break;
         }
     }
+
+    for (entity, piece) in pieces.iter() {
+        if taken_back.contains(&(piece.column, piece.row)) {
+            commands.entity(entity).despawn();
+        }
+    }
 }
 
 /// Determines if the game has been won. by the move at `col` and `row`, or if the game is drawn
 /// because the board is full. If so, sets the `state` to indicate which player won or that the
 /// game is drawn and returns `true`. If no-one has won and the game is not drawn, returns `false`.
 fn is_game_over(gd: &GameData) -> Option<GameOutcome> {
-    for r in 0..BOARD_ROWS {
-        for c in 0..BOARD_COLUMNS {
-            let p = gd.cells[r * BOARD_COLUMNS + c];
+    for r in 0..gd.rows {
+        for c in 0..gd.columns {
+            let p = gd.cells[r * gd.columns + c];
 
             if p.is_some() {
                 // total_pieces += 1;
@@ -371,60 +1705,770 @@ fn is_game_over(gd: &GameData) -> Option<GameOutcome> {
     None
 }
 
-/// Displays a message at the top of the play area indicating the game has been won by the stated
-/// player, or drawn.
-fn display_game_outcome(
-    commands: Commands,
-    gd: ResMut<GameData>,
-    asset_server: Res<AssetServer>,
-) {
-    match gd.game_outcome.unwrap() {
-        GameOutcome::ComputerWon => {
-            display_text(commands, asset_server, "Computer wins");
-        }
-        GameOutcome::Draw => {
-            display_text(commands, asset_server, "Game drawn");
-        }
-        GameOutcome::HumanWon => {
-            display_text(commands, asset_server, "You win!");
-        }
+/// Switches to the next piece theme when `THEME_KEY` is pressed.
+fn cycle_piece_theme(key: Res<Input<KeyCode>>, mut theme: ResMut<PieceTheme>) {
+    if key.just_pressed(THEME_KEY) {
+        select_next_theme(&mut theme);
     }
 }
 
-/// Displays the given text at the top-center of the window.
-fn display_text(mut commands: Commands, asset_server: Res<AssetServer>, s: &str) {
-    commands
-        .spawn(NodeBundle {
-            background_color: Color::rgba(0.0, 0.0, 0.0, 0.0).into(),
-            style: Style {
-                justify_content: JustifyContent::Center,
-                margin: UiRect {
-                    top: Val::Px(10.0),
-                    bottom: Val::Auto,
-                    left: Val::Auto,
-                    right: Val::Auto,
-                    ..Default::default()
-                },
-                ..Default::default()
-            },
-            ..Default::default()
-        })
-        .with_children(|parent| {
-            parent.spawn(TextBundle {
-                text: Text::from_section(
-                    s,
-                    TextStyle {
-                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                        font_size: 80.0,
-                        color: Color::rgb(0.6, 0.6, 1.0),
-                    },
-                ),
-                ..Default::default()
-            });
-        });
+/// Switches to the theme after `theme`, and saves it as the theme to use in future runs.
+fn select_next_theme(theme: &mut PieceTheme) {
+    *theme = theme.next();
+    settings::save_setting(SETTINGS_FILENAME, THEME_SETTING, theme.name());
+}
+
+/// Redraws every piece on the board when the piece theme changes.
+fn apply_piece_theme(theme: Res<PieceTheme>, mut pieces: Query<(&Piece, &mut TextureAtlasSprite)>) {
+    if !theme.is_changed() {
+        return;
+    }
+
+    for (piece, mut sprite) in pieces.iter_mut() {
+        *sprite = theme.sprite(piece.player);
+    }
+}
+
+/// Shows or hides the threat overlay when `THREAT_OVERLAY_KEY` is pressed.
+fn toggle_threat_overlay(key: Res<Input<KeyCode>>, mut overlay: ResMut<ThreatOverlay>) {
+    if key.just_pressed(THREAT_OVERLAY_KEY) {
+        overlay.visible = !overlay.visible;
+    }
+}
+
+/// Redraws the threat overlay whenever the board or the overlay's visibility changes. A small
+/// marker in a player's color is drawn over each cell where that player could complete a line on
+/// their next move. If a player has two or more such cells, they have a "double threat" that
+/// cannot be fully blocked, and larger markers are used instead. Where both players threaten the
+/// same cell, their markers are drawn side by side.
+fn update_threat_overlay(
+    mut commands: Commands,
+    gd: Res<GameData>,
+    overlay: Res<ThreatOverlay>,
+    theme: Res<PieceTheme>,
+    markers: Query<Entity, With<ThreatMarker>>,
+) {
+    if !gd.is_changed() && !overlay.is_changed() && !theme.is_changed() {
+        return;
+    }
+
+    for entity in markers.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    if !overlay.visible || gd.game_outcome.is_some() {
+        return;
+    }
+
+    let human_threats = gd.threats(Player::Human);
+    let computer_threats = gd.threats(Player::Computer);
+
+    for (player, threats, opponent_threats) in [
+        (Player::Human, &human_threats, &computer_threats),
+        (Player::Computer, &computer_threats, &human_threats),
+    ] {
+        let size = if threats.len() > 1 {
+            DOUBLE_THREAT_MARKER_SIZE
+        } else {
+            THREAT_MARKER_SIZE
+        };
+
+        for &(col, row) in threats {
+            let mut position = cell_position(&gd, col, row);
+            if opponent_threats.contains(&(col, row)) {
+                let shift = size / 2.0;
+                position.x += if player == Player::Human {
+                    -shift
+                } else {
+                    shift
+                };
+            }
+
+            commands
+                .spawn(SpriteSheetBundle {
+                    sprite: TextureAtlasSprite {
+                        custom_size: Some(Vec2::splat(size)),
+                        ..theme.sprite(player)
+                    },
+                    texture_atlas: gd.texture_atlas.clone(),
+                    transform: Transform::from_translation(position.extend(2.0)),
+                    ..Default::default()
+                })
+                .insert(ThreatMarker);
+        }
+    }
+}
+
+/// Starts recording the game that has just started.
+fn start_game_record(
+    mut log: ResMut<GameLog>,
+    gd: Res<GameData>,
+    game_match: Res<Match>,
+    rng: Res<SeededRng>,
+    time: Res<Time>,
+) {
+    let start_time_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64);
+
+    log.record = Some(GameRecord {
+        start_time_ms,
+        board: game_match.board,
+        columns: gd.columns,
+        rows: gd.rows,
+        first_player: game_match.starting_player,
+        computer_strategy: gd.computer_strategy,
+        start_position: gd.position_rows(),
+        seed: rng.seed(),
+        events: Vec::new(),
+        outcome: None,
+    });
+    log.logged_moves.clear();
+    log.started = time.elapsed();
+    log.turn_started = time.elapsed();
+}
+
+/// Adds the moves made and taken back since the last update to the record of the current game.
+fn record_moves(mut log: ResMut<GameLog>, gd: Res<GameData>, time: Res<Time>) {
+    if !gd.is_changed() {
+        return;
+    }
+
+    let unchanged = log
+        .logged_moves
+        .iter()
+        .zip(gd.moves.iter())
+        .take_while(|((c1, r1, _), (c2, r2))| c1 == c2 && r1 == r2)
+        .count();
+    if unchanged == log.logged_moves.len() && unchanged == gd.moves.len() {
+        return;
+    }
+
+    let now = time.elapsed();
+    let time_ms = (now - log.started).as_millis() as u64;
+    let thinking_time_ms = (now - log.turn_started).as_millis() as u64;
+    let log = log.as_mut();
+    let Some(record) = log.record.as_mut() else {
+        return;
+    };
+
+    while log.logged_moves.len() > unchanged {
+        let (column, row, player) = log.logged_moves.pop().unwrap();
+        record.events.push(GameEvent::TakeBack {
+            player,
+            column,
+            row,
+            time_ms,
+        });
+    }
+
+    for &(column, row) in &gd.moves[unchanged..] {
+        let player = gd.cells[row * gd.columns + column].unwrap();
+        log.logged_moves.push((column, row, player));
+        record.events.push(GameEvent::Move {
+            player,
+            column,
+            row,
+            time_ms,
+            thinking_time_ms,
+        });
+    }
+
+    log.turn_started = now;
+}
+
+/// Writes the record of the game that just ended to a new file in the log directory. The file is
+/// named after the time the game started.
+fn write_game_record(mut log: ResMut<GameLog>, gd: Res<GameData>) {
+    let Some(mut record) = log.record.take() else {
+        return;
+    };
+    record.outcome = gd.game_outcome;
+
+    let path = log
+        .dir
+        .join(format!("fourline-{}.json", record.start_time_ms));
+    let result = fs::create_dir_all(&log.dir).and_then(|_| {
+        let json = serde_json::to_string_pretty(&record)?;
+        fs::write(&path, json)
+    });
+
+    match result {
+        Ok(()) => info!("Game record written to {}", path.display()),
+        Err(e) => warn!("Cannot write game record to {}: {}", path.display(), e),
+    }
+}
+
+/// Returns the puzzles in `PUZZLE_FILENAME`, in the format described at the top of that file.
+fn load_puzzles() -> Vec<Puzzle> {
+    let path = FileAssetReader::get_base_path()
+        .join("assets")
+        .join(PUZZLE_FILENAME);
+    match fs::read_to_string(&path) {
+        Ok(contents) => parse_puzzles(&contents, &path.display().to_string()),
+        Err(e) => {
+            warn!("Cannot read puzzles from {}: {}", path.display(), e);
+            Vec::new()
+        }
+    }
+}
+
+/// Returns the puzzles in `contents`, in the format of the puzzle file. Puzzles that cannot be
+/// parsed, or cannot be won in the stated number of moves, are logged as being in `source` and
+/// skipped.
+fn parse_puzzles(contents: &str, source: &str) -> Vec<Puzzle> {
+    let board = BoardVariant::Standard;
+    let mut puzzles = Vec::new();
+    let mut lines = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+    while let Some(header) = lines.next() {
+        let Some(moves) = header
+            .strip_prefix("win in")
+            .and_then(|n| n.trim().parse().ok())
+        else {
+            warn!(
+                "Ignoring puzzle with malformed header in {}: {}",
+                source, header
+            );
+            continue;
+        };
+
+        // Rows are listed from the top down, but `cells` starts with the bottom row.
+        let mut cells = vec![None; board.columns() * board.rows()];
+        let mut valid = true;
+        for row in (0..board.rows()).rev() {
+            let line = lines.next().unwrap_or_default();
+            valid &= line.chars().count() == board.columns();
+
+            for (column, c) in line.chars().enumerate().take(board.columns()) {
+                cells[row * board.columns() + column] = match c {
+                    'X' => Some(Player::Human),
+                    'O' => Some(Player::Computer),
+                    '.' => None,
+                    _ => {
+                        valid = false;
+                        None
+                    }
+                };
+            }
+        }
+
+        let puzzle = Puzzle { moves, cells };
+        let mut gd = puzzle.new_game(Handle::default());
+        if !valid || gd.validate_position(Player::Human) != Ok(Player::Human) {
+            warn!(
+                "Ignoring puzzle {} in {}: malformed position",
+                puzzles.len() + 1,
+                source
+            );
+        } else if !gd.can_force_win(Player::Human, moves) {
+            warn!(
+                "Ignoring puzzle {} in {}: no forced win",
+                puzzles.len() + 1,
+                source
+            );
+        } else {
+            puzzles.push(puzzle);
+        }
+    }
+
+    puzzles
+}
+
+/// Shows the number of the puzzle being played and how many moves it must be won in.
+fn show_puzzle_title(
+    mut commands: Commands,
+    puzzle_mode: Res<PuzzleMode>,
+    asset_server: Res<AssetServer>,
+    strings: Res<StringTable>,
+) {
+    let title = strings.format(
+        "puzzle.title",
+        &[&(puzzle_mode.index + 1), &puzzle_mode.puzzle().moves],
+    );
+    let text = display_text(&mut commands, &asset_server, &title, 30.0, true);
+    commands.entity(text).insert(PuzzleText);
+}
+
+/// Removes the puzzle's title.
+fn hide_puzzle_title(mut commands: Commands, texts: Query<Entity, With<PuzzleText>>) {
+    for entity in texts.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// After each of the human's moves in a puzzle, checks that the human can still force a win within
+/// the moves they have left. If not, the computer has escaped and the puzzle has failed, so the
+/// game ends as a win for the computer.
+fn check_puzzle_move(
+    mut gd: ResMut<GameData>,
+    puzzle_mode: Res<PuzzleMode>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !gd.is_changed() || gd.current_player != Some(Player::Computer) {
+        return;
+    }
+
+    let human_moves = gd
+        .moves
+        .iter()
+        .filter(|&&(col, row)| gd.cells[row * gd.columns + col] == Some(Player::Human))
+        .count() as u32;
+    let moves_left = puzzle_mode.puzzle().moves.saturating_sub(human_moves);
+
+    if gd.clone().opponent_can_escape(Player::Human, moves_left) {
+        gd.current_player = None;
+        gd.game_outcome = Some(GameOutcome::ComputerWon);
+        next_state.set(GameState::GameOver);
+    }
+}
+
+/// Abandons the puzzle being played and returns to the menu when `MENU_KEY` is pressed, cancelling
+/// the computer's move if it is still being chosen.
+fn leave_puzzle(
+    mut commands: Commands,
+    key: Res<Input<KeyCode>>,
+    thinking: Option<Res<ComputerThinking>>,
+    pieces: Query<Entity, GameBoardFilter>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !key.just_pressed(MENU_KEY) {
+        return;
+    }
+
+    if let Some(thinking) = thinking {
+        cancel_computer_move(&mut commands, &thinking);
+    }
+    for entity in pieces.iter() {
+        commands.entity(entity).despawn();
+    }
+    commands.remove_resource::<PuzzleMode>();
+    next_state.set(GameState::Menu);
+}
+
+/// Displays whether the puzzle was solved, and how to continue.
+fn display_puzzle_result(
+    mut commands: Commands,
+    gd: Res<GameData>,
+    puzzle_mode: Res<PuzzleMode>,
+    asset_server: Res<AssetServer>,
+    strings: Res<StringTable>,
+) {
+    let solved = gd.game_outcome == Some(GameOutcome::HumanWon);
+    let (message, next_step) = match (solved, puzzle_mode.index + 1 < puzzle_mode.puzzles.len()) {
+        (true, true) => ("puzzle.solved", "puzzle.next"),
+        (true, false) => ("puzzle.solved", "puzzle.last"),
+        (false, _) => ("puzzle.failed", "puzzle.retry"),
+    };
+
+    let text = display_text(
+        &mut commands,
+        &asset_server,
+        strings.get(message),
+        80.0,
+        true,
+    );
+    commands.entity(text).insert(GameOverText);
+    let text = display_text(
+        &mut commands,
+        &asset_server,
+        strings.get(next_step),
+        30.0,
+        false,
+    );
+    commands.entity(text).insert(GameOverText);
+}
+
+/// When the user presses `NEXT_GAME_KEY`, clicks or taps after a puzzle, starts the next puzzle if
+/// it was solved, or the same puzzle again if not. Returns to the menu if the last puzzle was
+/// solved, or if `MENU_KEY` is pressed.
+fn start_next_puzzle(
+    mut commands: Commands,
+    key: Res<Input<KeyCode>>,
+    mut mouse_button_input: ResMut<Input<MouseButton>>,
+    touches: Res<Touches>,
+    gd: Res<GameData>,
+    mut puzzle_mode: ResMut<PuzzleMode>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if key.just_pressed(MENU_KEY) {
+        next_state.set(GameState::Menu);
+        return;
+    }
+
+    if !key.just_pressed(NEXT_GAME_KEY)
+        && !mouse_button_input.just_pressed(MouseButton::Left)
+        && !touches.any_just_pressed()
+    {
+        return;
+    }
+    mouse_button_input.reset(MouseButton::Left);
+
+    if gd.game_outcome == Some(GameOutcome::HumanWon) {
+        if puzzle_mode.index + 1 == puzzle_mode.puzzles.len() {
+            next_state.set(GameState::Menu);
+            return;
+        }
+        puzzle_mode.index += 1;
+    }
+
+    commands.insert_resource(puzzle_mode.puzzle().new_game(gd.texture_atlas.clone()));
+    next_state.set(GameState::Playing);
+}
+
+/// Adds the result of the game that just ended to the match score.
+fn record_game_outcome(gd: Res<GameData>, mut game_match: ResMut<Match>) {
+    game_match.record(gd.game_outcome.unwrap());
+}
+
+/// Displays a message at the top of the play area indicating the game has been won by the stated
+/// player, or drawn. A message at the bottom of the play area shows the match score if more than
+/// one game is being played, along with the result of the match if it is over, and how to continue.
+fn display_game_outcome(
+    mut commands: Commands,
+    gd: Res<GameData>,
+    game_match: Res<Match>,
+    asset_server: Res<AssetServer>,
+    strings: Res<StringTable>,
+) {
+    let message = match gd.game_outcome.unwrap() {
+        GameOutcome::ComputerWon => strings.get("game.computer_wins"),
+        GameOutcome::Draw => strings.get("game.drawn"),
+        GameOutcome::HumanWon => strings.get("game.human_wins"),
+    };
+    let text = display_text(&mut commands, &asset_server, message, 80.0, true);
+    commands.entity(text).insert(GameOverText);
+
+    let next_step = match (game_match.best_of, game_match.outcome()) {
+        (1, _) => strings.get("match.menu"),
+        (_, Some(GameOutcome::ComputerWon)) => strings.get("match.computer_wins"),
+        (_, Some(GameOutcome::Draw)) => strings.get("match.drawn"),
+        (_, Some(GameOutcome::HumanWon)) => strings.get("match.human_wins"),
+        (_, None) => strings.get("match.next_game"),
+    };
+
+    let status = if game_match.best_of == 1 {
+        next_step.to_string()
+    } else {
+        let score = strings.format(
+            "match.score",
+            &[
+                &game_match.human_wins,
+                &game_match.computer_wins,
+                &game_match.best_of,
+            ],
+        );
+        format!("{}\n{}", score, next_step)
+    };
+    let text = display_text(&mut commands, &asset_server, &status, 30.0, false);
+    commands.entity(text).insert(GameOverText);
+}
+
+/// When the user presses `NEXT_GAME_KEY`, clicks or taps, starts the next game of the match, or
+/// returns to the menu if the match is over.
+fn start_next_game(
+    mut commands: Commands,
+    key: Res<Input<KeyCode>>,
+    mut mouse_button_input: ResMut<Input<MouseButton>>,
+    touches: Res<Touches>,
+    gd: Res<GameData>,
+    game_match: Res<Match>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !key.just_pressed(NEXT_GAME_KEY)
+        && !mouse_button_input.just_pressed(MouseButton::Left)
+        && !touches.any_just_pressed()
+    {
+        return;
+    }
+    mouse_button_input.reset(MouseButton::Left);
+
+    if game_match.outcome().is_some() {
+        next_state.set(GameState::Menu);
+    } else {
+        commands.insert_resource(game_match.new_game(gd.texture_atlas.clone()));
+        next_state.set(GameState::Playing);
+    }
+}
+
+/// Removes the pieces and messages left over from the previous game.
+fn clear_previous_game(
+    mut commands: Commands,
+    pieces: Query<Entity, With<Piece>>,
+    texts: Query<Entity, With<GameOverText>>,
+) {
+    for entity in pieces.iter().chain(texts.iter()) {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Displays the given text horizontally centered at either the top or bottom of the window.
+/// Returns the entity holding the text, which can be despawned recursively to remove it.
+fn display_text(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    s: &str,
+    font_size: f32,
+    at_top: bool,
+) -> Entity {
+    let (top, bottom) = if at_top {
+        (Val::Px(10.0), Val::Auto)
+    } else {
+        (Val::Auto, Val::Px(10.0))
+    };
+
+    commands
+        .spawn(NodeBundle {
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.0).into(),
+            style: Style {
+                justify_content: JustifyContent::Center,
+                margin: UiRect {
+                    top,
+                    bottom,
+                    left: Val::Auto,
+                    right: Val::Auto,
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text::from_section(
+                    s,
+                    TextStyle {
+                        font: asset_server.load(FONT_ASSET_FILENAME),
+                        font_size,
+                        color: Color::rgb(0.6, 0.6, 1.0),
+                    },
+                )
+                .with_alignment(TextAlignment::Center),
+                ..Default::default()
+            });
+        })
+        .id()
+}
+
+/// Plays every computer strategy against every other, `games_per_pairing` times for each pairing,
+/// alternating which strategy moves first. Prints a table of the results, ordered by the number of
+/// points scored, where a win is worth 2 points and a draw 1.
+fn run_tournament(games_per_pairing: u32) {
+    let seed = rng::choose_seed().unwrap_or_else(|e| exit_with_usage(&e.to_string()));
+    println!("Random seed is {}", seed);
+    let mut rng = SeededRng::new(seed);
+
+    #[derive(Default)]
+    struct Standing {
+        won: u32,
+        drawn: u32,
+        lost: u32,
+    }
+
+    impl Standing {
+        fn points(&self) -> u32 {
+            self.won * 2 + self.drawn
+        }
+    }
+
+    let mut standings: Vec<(Strategy, Standing)> = Strategy::ALL
+        .into_iter()
+        .map(|s| (s, Standing::default()))
+        .collect();
+
+    for a in 0..standings.len() {
+        for b in a + 1..standings.len() {
+            for game in 0..games_per_pairing {
+                // The `Player` values only label the two sides. Strategy `a` plays as `Human` and
+                // strategy `b` as `Computer`.
+                let starting_player = if game % 2 == 0 {
+                    Player::Human
+                } else {
+                    Player::Computer
+                };
+
+                let outcome =
+                    play_computer_game(standings[a].0, standings[b].0, starting_player, &mut rng);
+
+                match outcome {
+                    GameOutcome::HumanWon => {
+                        standings[a].1.won += 1;
+                        standings[b].1.lost += 1;
+                    }
+                    GameOutcome::ComputerWon => {
+                        standings[a].1.lost += 1;
+                        standings[b].1.won += 1;
+                    }
+                    GameOutcome::Draw => {
+                        standings[a].1.drawn += 1;
+                        standings[b].1.drawn += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    standings.sort_by_key(|(_, standing)| std::cmp::Reverse(standing.points()));
+
+    println!(
+        "{:<10} {:>6} {:>6} {:>6} {:>6} {:>6}",
+        "Strategy", "Played", "Won", "Drawn", "Lost", "Points"
+    );
+    for (strategy, standing) in standings.iter() {
+        println!(
+            "{:<10} {:>6} {:>6} {:>6} {:>6} {:>6}",
+            strategy.name(),
+            standing.won + standing.drawn + standing.lost,
+            standing.won,
+            standing.drawn,
+            standing.lost,
+            standing.points()
+        );
+    }
+}
+
+/// Plays a complete game without displaying it, where `human_strategy` and `computer_strategy`
+/// choose the moves for `Player::Human` and `Player::Computer` respectively. Returns the result.
+fn play_computer_game(
+    human_strategy: Strategy,
+    computer_strategy: Strategy,
+    starting_player: Player,
+    rng: &mut fastrand::Rng,
+) -> GameOutcome {
+    let mut gd = GameData::new(
+        BoardVariant::default(),
+        Handle::default(),
+        starting_player,
+        computer_strategy,
+    );
+    let mut player = starting_player;
+
+    loop {
+        let strategy = match player {
+            Player::Human => human_strategy,
+            Player::Computer => computer_strategy,
+        };
+
+        let _ = gd.make_move(strategy.choose_column(&gd, player, rng), player);
+
+        if let Some(outcome) = is_game_over(&gd) {
+            return outcome;
+        }
+        player = player.opponent();
+    }
+}
+
+/// The settings chosen on the command line.
+struct Options {
+    best_of: u32,
+    computer_strategy: Strategy,
+    theme: Option<PieceTheme>,
+    language: Option<String>,
+    log_dir: Option<PathBuf>,
+    tournament: bool,
+}
+
+/// Parses the command line options described at the top of this file. Prints a message and exits
+/// if they are invalid.
+fn parse_options() -> Options {
+    let mut options = Options {
+        best_of: 1,
+        computer_strategy: Strategy::default(),
+        theme: None,
+        language: None,
+        log_dir: None,
+        tournament: false,
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--best-of" => {
+                options.best_of = match args.next().and_then(|n| n.parse().ok()) {
+                    Some(n) if n % 2 == 1 => n,
+                    _ => exit_with_usage("--best-of requires an odd number of games"),
+                };
+            }
+            "--ai" => {
+                options.computer_strategy =
+                    match args.next().and_then(|name| Strategy::from_name(&name)) {
+                        Some(strategy) => strategy,
+                        None => exit_with_usage("--ai requires one of: random, greedy, cautious"),
+                    };
+            }
+            "--theme" => {
+                options.theme = match args.next().and_then(|name| PieceTheme::from_name(&name)) {
+                    Some(theme) => Some(theme),
+                    None => exit_with_usage(
+                        "--theme requires one of: classic, color-safe, rings, symbols",
+                    ),
+                };
+            }
+            "--language" => {
+                options.language = match args.next().map(|code| code.to_lowercase()) {
+                    Some(code) if localization::is_language_code(&code) => Some(code),
+                    _ => exit_with_usage("--language requires a two letter language code"),
+                };
+            }
+            "--log-games" => {
+                options.log_dir = match args.next() {
+                    Some(dir) => Some(PathBuf::from(dir)),
+                    None => exit_with_usage("--log-games requires a directory"),
+                };
+            }
+            "--tournament" => options.tournament = true,
+            // The seed is read by `rng::choose_seed`, so is only checked here.
+            rng::SEED_OPTION => {
+                if args.next().and_then(|n| n.parse::<u64>().ok()).is_none() {
+                    exit_with_usage("--seed requires a whole number");
+                }
+            }
+            _ => exit_with_usage(&format!("unrecognized option '{}'", arg)),
+        }
+    }
+
+    options
+}
+
+fn exit_with_usage(message: &str) -> ! {
+    eprintln!("fourline: {}", message);
+    eprintln!(
+        "Usage: fourline [--best-of <N>] [--ai <random|greedy|cautious>] [--theme <THEME>] \
+        [--language <CODE>] [--log-games <DIR>] [--tournament] [--seed <N>]"
+    );
+    std::process::exit(1);
 }
 
 fn main() {
+    let options = parse_options();
+
+    if options.tournament {
+        run_tournament(TOURNAMENT_GAMES_PER_PAIRING);
+        return;
+    }
+
+    let saved_settings = settings::load_settings(SETTINGS_FILENAME);
+
+    let theme = options.theme.unwrap_or_else(|| {
+        saved_settings
+            .get(THEME_SETTING)
+            .and_then(|name| PieceTheme::from_name(name))
+            .unwrap_or_default()
+    });
+
+    let language = options
+        .language
+        .or_else(|| {
+            saved_settings
+                .get(LANGUAGE_SETTING)
+                .filter(|code| localization::is_language_code(code))
+                .cloned()
+        })
+        .or_else(localization::system_language)
+        .unwrap_or_else(|| localization::DEFAULT_LANGUAGE.to_string());
+
     let wd = Window {
         resolution: WindowResolution::new(WINDOW_WIDTH, WINDOW_HEIGHT),
         title: String::from("Fourline"),
@@ -436,9 +2480,436 @@ fn main() {
             primary_window: Some(wd),
             ..default()
         }))
+        .add_plugins(SeededRngPlugin)
         .add_state::<GameState>()
+        .init_resource::<ThreatOverlay>()
+        .init_resource::<ColumnSelection>()
+        .insert_resource(theme)
+        .insert_resource(StringTable::load(LOCALE_NAME, &language))
+        .insert_resource(MenuChoices {
+            best_of: options.best_of,
+            computer_strategy: options.computer_strategy,
+            starting_player: Player::Human,
+            board: BoardVariant::default(),
+        })
         .add_systems(Startup, setup)
-        .add_systems(Update, game_loop.run_if(in_state(GameState::Playing)))
-        .add_systems(OnEnter(GameState::GameOver), display_game_outcome)
+        .add_systems(OnEnter(GameState::Menu), (clear_board, spawn_menu))
+        .add_systems(
+            Update,
+            (menu_interaction, update_menu_text)
+                .chain()
+                .run_if(in_state(GameState::Menu)),
+        )
+        .add_systems(OnExit(GameState::Menu), (despawn_menu, start_match))
+        .add_systems(OnEnter(GameState::Editor), start_editor)
+        .add_systems(
+            Update,
+            (edit_position, update_editor, leave_editor)
+                .chain()
+                .run_if(in_state(GameState::Editor)),
+        )
+        .add_systems(OnExit(GameState::Editor), despawn_editor)
+        .add_systems(OnEnter(GameState::Playing), spawn_pieces)
+        .add_systems(
+            Update,
+            (
+                show_thinking_indicator,
+                computer_turn,
+                select_column,
+                game_loop,
+                show_touch_preview,
+                apply_deferred,
+                undo_or_restart,
+            )
+                .chain()
+                .run_if(in_state(GameState::Playing)),
+        )
+        .add_systems(
+            Update,
+            (toggle_threat_overlay, update_threat_overlay)
+                .chain()
+                .after(game_loop)
+                .run_if(resource_exists::<GameData>()),
+        )
+        .add_systems(Update, (cycle_piece_theme, apply_piece_theme).chain())
+        .add_systems(
+            OnEnter(GameState::GameOver),
+            (record_game_outcome, display_game_outcome)
+                .chain()
+                .run_if(not(resource_exists::<PuzzleMode>())),
+        )
+        .add_systems(
+            Update,
+            start_next_game
+                .run_if(in_state(GameState::GameOver))
+                .run_if(not(resource_exists::<PuzzleMode>())),
+        )
+        .add_systems(
+            OnEnter(GameState::Playing),
+            show_puzzle_title.run_if(resource_exists::<PuzzleMode>()),
+        )
+        .add_systems(
+            Update,
+            check_puzzle_move
+                .after(game_loop)
+                .run_if(in_state(GameState::Playing))
+                .run_if(resource_exists::<PuzzleMode>()),
+        )
+        .add_systems(
+            Update,
+            leave_puzzle
+                .after(undo_or_restart)
+                .after(check_puzzle_move)
+                .run_if(in_state(GameState::Playing))
+                .run_if(resource_exists::<PuzzleMode>()),
+        )
+        .add_systems(OnExit(GameState::Playing), hide_puzzle_title)
+        .add_systems(
+            OnEnter(GameState::GameOver),
+            display_puzzle_result.run_if(resource_exists::<PuzzleMode>()),
+        )
+        .add_systems(
+            Update,
+            start_next_puzzle
+                .run_if(in_state(GameState::GameOver))
+                .run_if(resource_exists::<PuzzleMode>()),
+        )
+        .add_systems(OnExit(GameState::GameOver), clear_previous_game)
+        .add_plugins(GameLogPlugin {
+            dir: options.log_dir,
+        })
         .run();
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::input::touch::TouchPhase;
+    use bevy::input::InputPlugin;
+
+    use super::*;
+
+    /// Returns a game on the standard board with the pieces in `rows`, listed from the top down as
+    /// in the puzzle file. Rows missing from the top are empty.
+    fn game_from_rows(rows: &[&str]) -> GameData {
+        let board = BoardVariant::Standard;
+        let mut gd = GameData::new(board, Handle::default(), Player::Human, Strategy::Random);
+        for (row, line) in rows.iter().rev().enumerate() {
+            for (column, c) in line.chars().enumerate() {
+                gd.cells[row * board.columns() + column] = match c {
+                    'X' => Some(Player::Human),
+                    'O' => Some(Player::Computer),
+                    _ => None,
+                };
+            }
+        }
+        gd
+    }
+
+    #[test]
+    fn position_to_move_from() {
+        let gd = game_from_rows(&[]);
+        assert_eq!(gd.validate_position(Player::Computer), Ok(Player::Computer));
+        let gd = game_from_rows(&["XO.X..."]);
+        assert_eq!(gd.validate_position(Player::Human), Ok(Player::Computer));
+        let gd = game_from_rows(&["O......", "XO....."]);
+        assert_eq!(gd.validate_position(Player::Computer), Ok(Player::Human));
+    }
+
+    #[test]
+    fn invalid_positions() {
+        let gd = game_from_rows(&["..X....", "XO.O..."]);
+        assert_eq!(
+            gd.validate_position(Player::Human),
+            Err(PositionError::Floating(2))
+        );
+        let gd = game_from_rows(&["XXX...O"]);
+        assert_eq!(
+            gd.validate_position(Player::Human),
+            Err(PositionError::PieceCounts)
+        );
+        let gd = game_from_rows(&["OOO....", "XXXX..."]);
+        assert_eq!(
+            gd.validate_position(Player::Human),
+            Err(PositionError::AlreadyWon)
+        );
+        let gd = game_from_rows(&[
+            "OOXXOXO", "XOOXXXO", "XXXOOOX", "OOOXXXO", "OOXXOXO", "XXOXXOO",
+        ]);
+        assert_eq!(
+            gd.validate_position(Player::Human),
+            Err(PositionError::BoardFull)
+        );
+    }
+
+    #[test]
+    fn forced_wins() {
+        // One move completes a line.
+        let mut gd = game_from_rows(&["XXX.OO."]);
+        assert!(gd.can_force_win(Player::Human, 1));
+        assert!(!gd.can_force_win(Player::Human, 0));
+
+        // Playing in column 3 threatens two cells, and only one can be blocked.
+        let mut gd = game_from_rows(&[".XX..OO"]);
+        assert!(!gd.can_force_win(Player::Human, 1));
+        assert!(gd.can_force_win(Player::Human, 2));
+
+        // The computer completes its own line before the human can.
+        let mut gd = game_from_rows(&["O......", "O......", "OXX...."]);
+        assert!(!gd.can_force_win(Player::Human, 2));
+
+        // Searching doesn't change the position.
+        assert_eq!(gd.moves, Vec::new());
+    }
+
+    #[test]
+    fn opponent_escapes() {
+        // A single threat can be blocked.
+        let mut gd = game_from_rows(&["XXX.OO."]);
+        assert!(gd.opponent_can_escape(Player::Human, 1));
+
+        // A double threat can't.
+        let mut gd = game_from_rows(&[".XXX.OO"]);
+        assert!(!gd.opponent_can_escape(Player::Human, 1));
+
+        // Filling the board draws the game, which escapes.
+        let mut gd = game_from_rows(&[
+            "OOXXOX.", "XOOXXXO", "XXXOOOX", "OOOXXXO", "OOXXOXO", "XXOXXOO",
+        ]);
+        assert!(gd.opponent_can_escape(Player::Computer, 1));
+    }
+
+    #[test]
+    fn puzzles_are_parsed() {
+        let puzzles = parse_puzzles(
+            "# A comment\n\n\
+            win in 1\n.......\n.......\n.......\n.......\n.O..O..\n.O..XXX\n\n\
+            win in 2\n.......\n.......\n.......\n.......\n.XX....\nOOOXO..\n",
+            "test",
+        );
+        assert_eq!(puzzles.len(), 2);
+        assert_eq!(puzzles[0].moves, 1);
+        assert_eq!(puzzles[1].moves, 2);
+        let gd = puzzles[1].new_game(Handle::default());
+        assert_eq!(
+            gd.position_rows()[4..],
+            [".XX....".to_string(), "OOOXO..".to_string()]
+        );
+    }
+
+    #[test]
+    fn malformed_and_illegal_puzzles_are_rejected() {
+        let valid = "win in 1\n.......\n.......\n.......\n.......\n.O..O..\n.O..XXX\n";
+        let rejected = [
+            // Malformed header.
+            "win in one\n.......\n.......\n.......\n.......\n.O..O..\n.O..XXX\n",
+            // Too short a row.
+            "win in 1\n.......\n.......\n.......\n.......\n.O..O.\n.O..XXX\n",
+            // Unknown piece.
+            "win in 1\n.......\n.......\n.......\n.......\n.O..Q..\n.O..XXX\n",
+            // A piece above an empty cell.
+            "win in 1\n.......\n.......\n.......\n.O.....\n....O..\n.O..XXX\n",
+            // Already won.
+            "win in 1\n.......\n.......\n.......\n.O.....\n.O..O..\n.O.XXXX\n",
+            // No forced win in the moves given.
+            "win in 1\n.......\n.......\n.......\n.......\n.O..O..\n.O..XX.\n",
+        ];
+
+        for puzzle in rejected {
+            let contents = format!("{}\n{}", puzzle, valid);
+            let puzzles = parse_puzzles(&contents, "test");
+            assert_eq!(puzzles.len(), 1, "accepted {:?}", puzzle);
+        }
+    }
+
+    /// Returns a best-of-`best_of` match with the given results so far.
+    fn match_with(best_of: u32, human_wins: u32, computer_wins: u32, draws: u32) -> Match {
+        let mut game_match = Match::new(&MenuChoices {
+            best_of,
+            computer_strategy: Strategy::Random,
+            starting_player: Player::Human,
+            board: BoardVariant::Standard,
+        });
+        game_match.human_wins = human_wins;
+        game_match.computer_wins = computer_wins;
+        game_match.draws = draws;
+        game_match
+    }
+
+    #[test]
+    fn best_of_three_is_decided_by_two_wins() {
+        assert_eq!(match_with(3, 1, 0, 0).outcome(), None);
+        assert_eq!(match_with(3, 1, 1, 0).outcome(), None);
+        assert_eq!(
+            match_with(3, 2, 0, 0).outcome(),
+            Some(GameOutcome::HumanWon)
+        );
+        assert_eq!(
+            match_with(3, 1, 2, 0).outcome(),
+            Some(GameOutcome::ComputerWon)
+        );
+    }
+
+    #[test]
+    fn match_with_equal_wins_is_drawn() {
+        assert_eq!(match_with(3, 1, 1, 1).outcome(), Some(GameOutcome::Draw));
+        assert_eq!(match_with(3, 0, 0, 3).outcome(), Some(GameOutcome::Draw));
+        assert_eq!(match_with(1, 0, 0, 1).outcome(), Some(GameOutcome::Draw));
+    }
+
+    #[test]
+    fn match_ends_when_the_games_left_cannot_change_the_result() {
+        // The computer can't catch up by winning the one game left.
+        assert_eq!(
+            match_with(5, 2, 0, 2).outcome(),
+            Some(GameOutcome::HumanWon)
+        );
+        assert_eq!(
+            match_with(5, 0, 2, 2).outcome(),
+            Some(GameOutcome::ComputerWon)
+        );
+        // Winning the game left would draw the match, so it must be played.
+        assert_eq!(match_with(5, 2, 1, 1).outcome(), None);
+    }
+
+    #[test]
+    fn horizontal_threat() {
+        let gd = game_from_rows(&["XXX.OO."]);
+        assert_eq!(gd.threats(Player::Human), vec![(3, 0)]);
+        assert!(gd.threats(Player::Computer).is_empty());
+    }
+
+    #[test]
+    fn vertical_threat() {
+        let gd = game_from_rows(&["X......", "XO.....", "XO....."]);
+        assert_eq!(gd.threats(Player::Human), vec![(0, 3)]);
+        assert!(gd.threats(Player::Computer).is_empty());
+    }
+
+    #[test]
+    fn diagonal_threat() {
+        let gd = game_from_rows(&["..XO...", ".XOX...", "XOOX..."]);
+        assert_eq!(gd.threats(Player::Human), vec![(3, 3)]);
+    }
+
+    #[test]
+    fn double_threat() {
+        let gd = game_from_rows(&[".XXX.OO"]);
+        assert_eq!(gd.threats(Player::Human), vec![(0, 0), (4, 0)]);
+    }
+
+    #[test]
+    fn threat_above_an_empty_cell_is_not_flagged() {
+        let gd = game_from_rows(&["XXX....", "OOX...."]);
+        assert!(gd.threats(Player::Human).is_empty());
+    }
+
+    /// Returns an app that runs `select_column` and `show_touch_preview` during the human's turn
+    /// on an empty standard board, so they can be driven by synthetic touch events.
+    fn touch_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(InputPlugin)
+            .init_resource::<ColumnSelection>()
+            .init_resource::<PieceTheme>()
+            .insert_resource(game_from_rows(&[]))
+            .add_systems(Update, (select_column, show_touch_preview).chain());
+        app.world.spawn((
+            Window {
+                resolution: WindowResolution::new(WINDOW_WIDTH, WINDOW_HEIGHT),
+                ..Default::default()
+            },
+            PrimaryWindow,
+        ));
+        app.world.spawn((Transform::default(), PrimaryCamera));
+        app
+    }
+
+    /// Sends a touch event with `phase` over the middle of `column`, or to the left of the board if
+    /// `column` is `None`, and runs the app for a frame.
+    fn touch(app: &mut App, phase: TouchPhase, column: Option<usize>) {
+        let x = match column {
+            Some(column) => (column as f32 + 0.5) * SPRITE_WIDTH as f32,
+            None => -0.5 * SPRITE_WIDTH as f32,
+        };
+        let board_width = BoardVariant::Standard.columns() as f32 * SPRITE_WIDTH as f32;
+        let board_left = (WINDOW_WIDTH - board_width) / 2.0;
+        app.world.send_event(TouchInput {
+            phase,
+            position: Vec2::new(board_left + x, WINDOW_HEIGHT / 2.0),
+            force: None,
+            id: 1,
+        });
+        app.update();
+    }
+
+    /// Returns the column chosen and the column touched in the last frame.
+    fn selection(app: &App) -> (Option<usize>, Option<usize>) {
+        let selection = app.world.resource::<ColumnSelection>();
+        (selection.chosen, selection.touched)
+    }
+
+    /// Returns the positions of the touch previews shown.
+    fn previews(app: &mut App) -> Vec<Vec3> {
+        app.world
+            .query_filtered::<&Transform, With<TouchPreview>>()
+            .iter(&app.world)
+            .map(|transform| transform.translation)
+            .collect()
+    }
+
+    /// Returns where the touch preview is shown for a piece dropped into empty `column`.
+    fn preview_position(app: &App, column: usize) -> Vec3 {
+        cell_position(app.world.resource::<GameData>(), column, 0).extend(0.0)
+    }
+
+    #[test]
+    fn touch_preview_follows_the_finger() {
+        let mut app = touch_app();
+
+        touch(&mut app, TouchPhase::Started, Some(2));
+        assert_eq!(selection(&app), (None, Some(2)));
+        assert_eq!(previews(&mut app), vec![preview_position(&app, 2)]);
+
+        touch(&mut app, TouchPhase::Moved, Some(5));
+        assert_eq!(selection(&app), (None, Some(5)));
+        assert_eq!(previews(&mut app), vec![preview_position(&app, 5)]);
+    }
+
+    #[test]
+    fn touch_chooses_the_column_it_is_lifted_over() {
+        let mut app = touch_app();
+
+        touch(&mut app, TouchPhase::Started, Some(3));
+        touch(&mut app, TouchPhase::Moved, Some(4));
+        assert_eq!(selection(&app), (None, Some(4)));
+
+        touch(&mut app, TouchPhase::Ended, Some(4));
+        assert_eq!(selection(&app), (Some(4), None));
+        assert!(previews(&mut app).is_empty());
+
+        app.update();
+        assert_eq!(selection(&app), (None, None));
+    }
+
+    #[test]
+    fn cancelled_touch_chooses_nothing() {
+        let mut app = touch_app();
+
+        touch(&mut app, TouchPhase::Started, Some(1));
+        touch(&mut app, TouchPhase::Canceled, Some(1));
+        assert_eq!(selection(&app), (None, None));
+        assert!(previews(&mut app).is_empty());
+    }
+
+    #[test]
+    fn touch_lifted_off_the_board_chooses_nothing() {
+        let mut app = touch_app();
+
+        touch(&mut app, TouchPhase::Started, Some(1));
+        touch(&mut app, TouchPhase::Moved, None);
+        assert_eq!(selection(&app), (None, None));
+        assert!(previews(&mut app).is_empty());
+
+        touch(&mut app, TouchPhase::Ended, None);
+        assert_eq!(selection(&app), (None, None));
+    }
+}
@@ -1,8 +1,10 @@
 /// Create two cubes, a light source, and a camera, and position them such that the main cube is
 /// visible from the camera. The main cube rotates, the other doesn't. This is controlled by
 /// adding the `RotatingEntity` component to the main cube. Theoretically, the `rotate_entities`
-/// system should rotate all types of entities that have this component.
+/// system should rotate all types of entities that have this component. The camera has a
+/// first-person controller, so the cubes can be viewed from any angle.
 use bevy::prelude::*;
+use bevy_demos::first_person_camera::{FirstPersonCamera, FirstPersonCameraPlugin};
 
 #[derive(Component)]
 struct RotatingEntity; // Component to indicate entity should be rotated
@@ -43,10 +45,12 @@ fn setup(
     });
 
     // Camera
-    commands.spawn(Camera3dBundle {
-        transform: Transform::from_xyz(0.0, 1.0, 0.0),
-        ..Default::default()
-    });
+    commands
+        .spawn(Camera3dBundle {
+            transform: Transform::from_xyz(0.0, 1.0, 0.0),
+            ..Default::default()
+        })
+        .insert(FirstPersonCamera::default());
 }
 
 fn rotate_entities(time: Res<Time>, mut query: Query<&mut Transform, With<RotatingEntity>>) {
@@ -58,6 +62,7 @@ fn rotate_entities(time: Res<Time>, mut query: Query<&mut Transform, With<Rotati
 fn main() {
     App::new()
         .add_plugins(DefaultPlugins)
+        .add_plugins(FirstPersonCameraPlugin)
         .add_systems(Startup, setup)
         .add_systems(Update, rotate_entities)
         .run();
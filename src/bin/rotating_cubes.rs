@@ -1,62 +1,24 @@
-/// Create several rotating cubes, a light source, and a camera. The rotation is controlled by
-/// adding the `RotatingEntity` component to the entities to be rotated.
+/// Create several rotating cubes, a light source, and a camera. The scene is described in
+/// `assets/scenes/rotating_cubes.scene.ron`, where each cube is given the speed it turns at, and
+/// changes to the file are shown while the demo is running. The camera can be moved among the
+/// cubes with the mouse and keyboard.
 use bevy::prelude::*;
+use bevy_demos::first_person_camera::FirstPersonCameraPlugin;
+use bevy_demos::scene_description::{DescribedScene, SceneDescriptionPlugin};
 
-#[derive(Component)]
-struct RotatingEntity; // Component to indicate entity should be rotated
+const SCENE_FILENAME: &str = "scenes/rotating_cubes.scene.ron";
 
-fn setup(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    // Create and add a default material
-    let material_handle = materials.add(StandardMaterial {
-        base_color: Color::rgb(0.7, 0.6, 0.7),
-        ..Default::default()
-    });
-
-    let cube_locations = [
-        Vec3::new(0.0, 0.0, -5.0),
-        Vec3::new(-4.0, 0.0, -9.0),
-        Vec3::new(3.0, 0.0, -13.0),
-        Vec3::new(-1.0, 0.0, -17.0),
-    ];
-
-    for location in cube_locations.iter() {
-        commands
-            .spawn(PbrBundle {
-                mesh: meshes.add(Mesh::from(shape::Cube::new(1.0))),
-                material: material_handle.clone(),
-                transform: Transform::from_translation(*location),
-                ..Default::default()
-            })
-            .insert(RotatingEntity);
-    }
-
-    // Light
-    commands.spawn(PointLightBundle {
-        transform: Transform::from_xyz(2.0, 5.0, 2.0),
-        ..Default::default()
-    });
-
-    // Camera
-    commands.spawn(Camera3dBundle {
-        transform: Transform::from_xyz(0.0, 1.0, 0.0),
-        ..Default::default()
-    });
-}
-
-fn rotate_entities(time: Res<Time>, mut query: Query<&mut Transform, With<RotatingEntity>>) {
-    for mut transform in query.iter_mut() {
-        transform.rotation = Quat::from_rotation_y(time.elapsed_seconds() as f32 / 2.0);
-    }
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(SpatialBundle::default())
+        .insert(DescribedScene::new(asset_server.load(SCENE_FILENAME)));
 }
 
 fn main() {
     App::new()
         .add_plugins(DefaultPlugins)
+        .add_plugins(FirstPersonCameraPlugin)
+        .add_plugins(SceneDescriptionPlugin)
         .add_systems(Startup, setup)
-        .add_systems(Update, rotate_entities)
         .run();
 }
@@ -0,0 +1,178 @@
+/// Spawn a large forest to measure how fast trees are drawn. The frame rate is logged every second.
+/// Trees share the two meshes in `TreeMeshes`, and distant trees are drawn in less detail by
+/// `TreeLodPlugin`. Either can be turned off to compare the frame rate with and without it. The
+/// camera is controlled as in first_person_movement, and starts above the forest.
+///
+/// Command line options:
+///   --trees <N>         Spawn N trees. The default is 10000.
+///   --no-lod            Draw every tree in full detail.
+///   --unique-meshes     Give each tree its own meshes rather than sharing them, as trees used to.
+///                       Implies --no-lod.
+///   --seed <N>          Seed the random number generator, to place the same trees as a previous
+///                       run.
+use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
+use bevy::prelude::*;
+use bevy_demos::first_person_camera::{FirstPersonCamera, FirstPersonCameraPlugin};
+use bevy_demos::forest::{generate_forest, ForestSettings, TreeMeshes, CROWN_SIDES};
+use bevy_demos::pyramid::Pyramid;
+use bevy_demos::rng::{self, SeededRng, SeededRngPlugin};
+use bevy_demos::tree_lod::{spawn_tree, TreeLodPlugin, TreeOptions};
+
+/// The number of trees spawned if `--trees` isn't given.
+const DEFAULT_TREES: usize = 10_000;
+
+/// The number of trees per unit of area. The forest is made large enough to hold the trees asked
+/// for at this density.
+const DENSITY: f32 = 0.2;
+
+/// The command line options.
+#[derive(Resource)]
+struct Options {
+    trees: usize,
+    lod: bool,
+    unique_meshes: bool,
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    tree_meshes: Res<TreeMeshes>,
+    options: Res<Options>,
+    mut rng: ResMut<SeededRng>,
+) {
+    let material_handle_crown = materials.add(StandardMaterial {
+        base_color: Color::rgb(0.3, 0.8, 0.3),
+        ..Default::default()
+    });
+
+    let material_handle_trunk = materials.add(StandardMaterial {
+        base_color: Color::rgb(0.5, 0.3, 0.3),
+        ..Default::default()
+    });
+
+    let half_size = (options.trees as f32 / DENSITY).sqrt() / 2.;
+    let forest = generate_forest(
+        &ForestSettings {
+            area: Rect::new(-half_size, -half_size, half_size, half_size),
+            density: DENSITY,
+            min_spacing: 1.5,
+            clearing: (Vec2::ZERO, 0.),
+            ..Default::default()
+        },
+        &mut rng,
+    );
+
+    commands
+        .spawn(SpatialBundle::default())
+        .with_children(|parent| {
+            for tree in forest.iter() {
+                // Each tree either scales the shared meshes, or adds meshes of its own dimensions.
+                let tree_meshes = if options.unique_meshes {
+                    TreeMeshes {
+                        trunk: meshes.add(Mesh::from(shape::Box::new(
+                            tree.trunk_width,
+                            tree.trunk_height,
+                            tree.trunk_width,
+                        ))),
+                        crown: meshes.add(Mesh::from(Pyramid::new(
+                            CROWN_SIDES,
+                            tree.crown_width,
+                            tree.crown_height,
+                        ))),
+                    }
+                } else {
+                    tree_meshes.clone()
+                };
+                spawn_tree(
+                    parent,
+                    &tree_meshes,
+                    &material_handle_crown,
+                    &material_handle_trunk,
+                    tree,
+                    TreeOptions {
+                        lod: options.lod,
+                        scale_meshes: !options.unique_meshes,
+                        ..Default::default()
+                    },
+                );
+            }
+        });
+
+    info!(
+        "Spawned {} trees using {} meshes",
+        forest.len(),
+        meshes.len()
+    );
+
+    // Sun
+    commands.spawn(DirectionalLightBundle {
+        transform: Transform::from_xyz(1., 2., 1.).looking_at(Vec3::ZERO, Vec3::Y),
+        ..Default::default()
+    });
+
+    // Camera, above one corner of the forest and looking across it
+    commands
+        .spawn(Camera3dBundle {
+            transform: Transform::from_xyz(-half_size, 15., half_size)
+                .looking_at(Vec3::ZERO, Vec3::Y),
+            ..Default::default()
+        })
+        .insert(FirstPersonCamera::default());
+}
+
+/// Returns the options given on the command line. Exits with a usage message if they are invalid.
+fn parse_options() -> Options {
+    let mut options = Options {
+        trees: DEFAULT_TREES,
+        lod: true,
+        unique_meshes: false,
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--trees" => {
+                options.trees = match args.next().and_then(|n| n.parse().ok()) {
+                    Some(n) => n,
+                    None => exit_with_usage("--trees requires a number of trees"),
+                };
+            }
+            "--no-lod" => options.lod = false,
+            "--unique-meshes" => {
+                options.unique_meshes = true;
+                options.lod = false;
+            }
+            // The seed is read by `rng::choose_seed`, so is only checked here.
+            rng::SEED_OPTION => {
+                if args.next().and_then(|n| n.parse::<u64>().ok()).is_none() {
+                    exit_with_usage("--seed requires a whole number");
+                }
+            }
+            _ => exit_with_usage(&format!("unrecognized option '{}'", arg)),
+        }
+    }
+
+    options
+}
+
+/// Prints `message` and the command line options, then exits.
+fn exit_with_usage(message: &str) -> ! {
+    eprintln!("tree_stress_test: {}", message);
+    eprintln!("Usage: tree_stress_test [--trees <N>] [--no-lod] [--unique-meshes] [--seed <N>]");
+    std::process::exit(1);
+}
+
+fn main() {
+    let options = parse_options();
+
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .add_plugins((FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin::default()))
+        .add_plugins(FirstPersonCameraPlugin)
+        .add_plugins(SeededRngPlugin)
+        .add_plugins(TreeLodPlugin)
+        .insert_resource(options)
+        .add_systems(Startup, setup)
+        .run();
+}
@@ -0,0 +1,255 @@
+//! A day and night cycle. A directional light, the sun, moves across the sky over the length of a
+//! day, and its color and brightness, the ambient light and the color of the sky change between
+//! night, dawn, noon and dusk. If there is a `Sky` resource, its colors are changed too. The keys
+//! in `DayNightSettings` pause time and make it pass faster or slower.
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+
+use crate::key_bindings::rebinding_screen_closed;
+use crate::sky::Sky;
+
+/// The time of day at the start, as a fraction of a day. This is mid-morning.
+const START_TIME: f32 = 0.35;
+
+/// The fastest and slowest that time can be made to pass, relative to `day_length`.
+const MAX_SPEED: f32 = 64.0;
+const MIN_SPEED: f32 = 1.0 / 16.0;
+
+/// How the lighting looks at one time of day. The lighting between two of these is blended.
+struct Keyframe {
+    /// The time of day, as a fraction of a day from midnight.
+    time: f32,
+    sun_color: Color,
+    /// The illuminance of the sun, in lux.
+    sun_illuminance: f32,
+    ambient_color: Color,
+    ambient_brightness: f32,
+    /// The color of the sky at the horizon.
+    horizon_color: Color,
+    /// The color of the sky overhead.
+    zenith_color: Color,
+}
+
+/// The lighting through the day, in order of time. The first and last are blended across
+/// midnight.
+const KEYFRAMES: [Keyframe; 6] = [
+    // Night
+    Keyframe {
+        time: 0.2,
+        sun_color: Color::rgb(1.0, 0.5, 0.3),
+        sun_illuminance: 0.0,
+        ambient_color: Color::rgb(0.4, 0.45, 0.8),
+        ambient_brightness: 0.05,
+        horizon_color: Color::rgb(0.01, 0.01, 0.05),
+        zenith_color: Color::rgb(0.0, 0.0, 0.02),
+    },
+    // Dawn
+    Keyframe {
+        time: 0.27,
+        sun_color: Color::rgb(1.0, 0.6, 0.4),
+        sun_illuminance: 5000.0,
+        ambient_color: Color::rgb(1.0, 0.75, 0.65),
+        ambient_brightness: 0.15,
+        horizon_color: Color::rgb(0.9, 0.55, 0.4),
+        zenith_color: Color::rgb(0.3, 0.35, 0.6),
+    },
+    // Morning
+    Keyframe {
+        time: 0.35,
+        sun_color: Color::rgb(1.0, 0.95, 0.85),
+        sun_illuminance: 15000.0,
+        ambient_color: Color::rgb(1.0, 1.0, 0.8),
+        ambient_brightness: 0.3,
+        horizon_color: Color::rgb(0.45, 0.65, 0.95),
+        zenith_color: Color::rgb(0.2, 0.4, 0.85),
+    },
+    // Afternoon
+    Keyframe {
+        time: 0.65,
+        sun_color: Color::rgb(1.0, 0.95, 0.85),
+        sun_illuminance: 15000.0,
+        ambient_color: Color::rgb(1.0, 1.0, 0.8),
+        ambient_brightness: 0.3,
+        horizon_color: Color::rgb(0.45, 0.65, 0.95),
+        zenith_color: Color::rgb(0.2, 0.4, 0.85),
+    },
+    // Dusk
+    Keyframe {
+        time: 0.73,
+        sun_color: Color::rgb(1.0, 0.5, 0.3),
+        sun_illuminance: 5000.0,
+        ambient_color: Color::rgb(1.0, 0.65, 0.55),
+        ambient_brightness: 0.15,
+        horizon_color: Color::rgb(0.95, 0.45, 0.3),
+        zenith_color: Color::rgb(0.25, 0.25, 0.55),
+    },
+    // Night
+    Keyframe {
+        time: 0.8,
+        sun_color: Color::rgb(1.0, 0.5, 0.3),
+        sun_illuminance: 0.0,
+        ambient_color: Color::rgb(0.4, 0.45, 0.8),
+        ambient_brightness: 0.05,
+        horizon_color: Color::rgb(0.01, 0.01, 0.05),
+        zenith_color: Color::rgb(0.0, 0.0, 0.02),
+    },
+];
+
+/// How the day passes, and the keys that control it.
+#[derive(Clone, Debug, Resource)]
+pub struct DayNightSettings {
+    /// The length of a day, in seconds, when time passes at normal speed.
+    pub day_length: f32,
+    /// The key that pauses time, or restarts it if it is paused.
+    pub pause_key: KeyCode,
+    /// The key that doubles the speed time passes at.
+    pub faster_key: KeyCode,
+    /// The key that halves the speed time passes at.
+    pub slower_key: KeyCode,
+}
+
+impl Default for DayNightSettings {
+    fn default() -> Self {
+        Self {
+            day_length: 240.0,
+            pause_key: KeyCode::P,
+            faster_key: KeyCode::BracketRight,
+            slower_key: KeyCode::BracketLeft,
+        }
+    }
+}
+
+/// The time of day, and how fast it passes.
+#[derive(Clone, Debug, Resource)]
+pub struct TimeOfDay {
+    /// The fraction of the day since midnight, so 0.5 is noon.
+    pub time: f32,
+    /// How many times faster than normal time passes.
+    pub speed: f32,
+    pub paused: bool,
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self {
+            time: START_TIME,
+            speed: 1.0,
+            paused: false,
+        }
+    }
+}
+
+/// Marks the directional light that acts as the sun.
+#[derive(Component)]
+pub struct Sun;
+
+/// Adds the sun, `DayNightSettings` and `TimeOfDay`, and the systems that move the sun and change
+/// the lighting and sky color as the day passes.
+pub struct DayNightPlugin;
+
+impl Plugin for DayNightPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DayNightSettings>()
+            .init_resource::<TimeOfDay>()
+            .add_systems(Startup, spawn_sun)
+            .add_systems(
+                Update,
+                (
+                    control_time.run_if(rebinding_screen_closed),
+                    pass_time,
+                    update_lighting,
+                )
+                    .chain(),
+            );
+    }
+}
+
+fn spawn_sun(mut commands: Commands) {
+    commands
+        .spawn(DirectionalLightBundle {
+            directional_light: DirectionalLight {
+                shadows_enabled: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(Sun);
+}
+
+/// Pauses time, or changes how fast it passes, when the keys in `DayNightSettings` are pressed.
+/// This doesn't run while the rebinding screen is open, so binding one of the keys doesn't also
+/// change the time.
+fn control_time(
+    key: Res<Input<KeyCode>>,
+    settings: Res<DayNightSettings>,
+    mut time_of_day: ResMut<TimeOfDay>,
+) {
+    if key.just_pressed(settings.pause_key) {
+        time_of_day.paused = !time_of_day.paused;
+    }
+
+    let speed = if key.just_pressed(settings.faster_key) {
+        time_of_day.speed * 2.0
+    } else if key.just_pressed(settings.slower_key) {
+        time_of_day.speed / 2.0
+    } else {
+        return;
+    };
+    time_of_day.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+    info!("Time passes {}x faster than normal", time_of_day.speed);
+}
+
+/// Advances the time of day, unless time is paused.
+fn pass_time(time: Res<Time>, settings: Res<DayNightSettings>, mut time_of_day: ResMut<TimeOfDay>) {
+    if time_of_day.paused || settings.day_length <= 0.0 {
+        return;
+    }
+
+    let elapsed = time.delta_seconds() * time_of_day.speed / settings.day_length;
+    time_of_day.time = (time_of_day.time + elapsed).rem_euclid(1.0);
+}
+
+/// Moves the sun to its position for the time of day, and blends the lighting and sky color of
+/// the keyframes either side of the time of day.
+fn update_lighting(
+    time_of_day: Res<TimeOfDay>,
+    mut suns: Query<(&mut DirectionalLight, &mut Transform), With<Sun>>,
+    mut ambient: ResMut<AmbientLight>,
+    mut clear_color: ResMut<ClearColor>,
+    sky: Option<ResMut<Sky>>,
+) {
+    if !time_of_day.is_changed() {
+        return;
+    }
+    let time = time_of_day.time;
+
+    // Find the keyframes either side of `time`, wrapping around midnight.
+    let next_index = KEYFRAMES.iter().position(|k| k.time > time).unwrap_or(0);
+    let previous = &KEYFRAMES[(next_index + KEYFRAMES.len() - 1) % KEYFRAMES.len()];
+    let next = &KEYFRAMES[next_index];
+    let span = (next.time - previous.time).rem_euclid(1.0);
+    let t = (time - previous.time).rem_euclid(1.0) / span;
+
+    let blend = |a: Color, b: Color| Color::from(Vec4::from(a).lerp(Vec4::from(b), t));
+
+    // The sun rises in the +X direction at a quarter of the day, and sets in the -X direction at
+    // three quarters. It is tilted towards +Z so that it is never straight overhead.
+    let angle = (time - 0.25) * TAU;
+    let sun_position = Vec3::new(angle.cos(), angle.sin(), 0.4);
+    for (mut light, mut transform) in suns.iter_mut() {
+        light.color = blend(previous.sun_color, next.sun_color);
+        light.illuminance =
+            previous.sun_illuminance + t * (next.sun_illuminance - previous.sun_illuminance);
+        *transform = Transform::default().looking_at(-sun_position, Vec3::Y);
+    }
+
+    ambient.color = blend(previous.ambient_color, next.ambient_color);
+    ambient.brightness =
+        previous.ambient_brightness + t * (next.ambient_brightness - previous.ambient_brightness);
+    clear_color.0 = blend(previous.horizon_color, next.horizon_color);
+    if let Some(mut sky) = sky {
+        sky.horizon = clear_color.0;
+        sky.zenith = blend(previous.zenith_color, next.zenith_color);
+    }
+}
@@ -0,0 +1,545 @@
+//! A first-person camera controller shared by the 3D demos. Adding a `FirstPersonCamera` component
+//! to a camera lets the user turn it with the mouse, once the cursor has been grabbed by clicking
+//! in the window, and move it with the keys bound to the movement `Action`s. How the camera
+//! responds is set by the `FirstPersonCameraSettings` resource. Cameras either fly freely, or walk
+//! on the ground under gravity; the `ToggleWalking` action switches between the two. Either way,
+//! cameras are treated as a cylinder that slides around entities with a `Collider` component.
+//! Walking cameras stand on entities with a `Terrain` component, or on a flat ground plane where
+//! there are none.
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+use bevy::input::mouse::MouseMotion;
+use bevy::prelude::*;
+use bevy::window::{CursorGrabMode, PrimaryWindow};
+
+use crate::key_bindings::{rebinding_screen_closed, Action, KeyBindings, KeyBindingsPlugin};
+use crate::terrain::Terrain;
+
+/// The furthest the camera can look up or down by default, in radians. This is just short of
+/// vertical, as looking straight up or down makes the direction the camera faces ambiguous.
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+/// How the angle the camera turns depends on the speed of the mouse. The speed is measured in
+/// pixels per second, and the turn is multiplied by the factor given for each curve.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AccelerationCurve {
+    /// The turn is proportional to the distance the mouse moves, however fast it moves.
+    #[default]
+    Off,
+    /// The turn is multiplied by `1 + gain * speed`.
+    Linear { gain: f32 },
+    /// The turn is multiplied by `1 + gain * speed * speed`.
+    Quadratic { gain: f32 },
+}
+
+impl AccelerationCurve {
+    /// Returns the factor the turn is multiplied by when the mouse moves at `speed`.
+    pub fn factor(self, speed: f32) -> f32 {
+        match self {
+            AccelerationCurve::Off => 1.0,
+            AccelerationCurve::Linear { gain } => 1.0 + gain * speed,
+            AccelerationCurve::Quadratic { gain } => 1.0 + gain * speed * speed,
+        }
+    }
+}
+
+/// How the movement keys move a camera.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum MovementMode {
+    /// The camera moves in the direction it faces, and can move up and down freely.
+    #[default]
+    Fly,
+    /// The camera moves horizontally, is pulled down by gravity, and stays `eye_height` above the
+    /// ground.
+    Walk,
+}
+
+/// How cameras with a `FirstPersonCamera` component respond to the user.
+#[derive(Clone, Debug, Resource)]
+pub struct FirstPersonCameraSettings {
+    /// Whether the cameras respond to the user at all. Disabling the controller releases the
+    /// cursor.
+    pub enabled: bool,
+    /// Whether the cameras fly or walk.
+    pub mode: MovementMode,
+    /// The distance moved each second while a movement key is held.
+    pub move_speed: f32,
+    /// The downward acceleration of walking cameras, in distance per second squared.
+    pub gravity: f32,
+    /// The upward speed of a walking camera when it jumps.
+    pub jump_speed: f32,
+    /// The height of a walking camera above the ground.
+    pub eye_height: f32,
+    /// The height of the ground plane that walking cameras stand on where there is no `Terrain`.
+    pub ground_height: f32,
+    /// The radius of the cylinder that collides with `Collider`s. The cylinder reaches from the
+    /// camera down to `eye_height` below it.
+    pub body_radius: f32,
+    /// The angle, in radians, the camera turns for each pixel the mouse moves.
+    pub mouse_sensitivity: f32,
+    /// If `true`, moving the mouse forward turns the camera down rather than up.
+    pub invert_y: bool,
+    /// The lowest pitch, in radians, i.e., how far the camera can look down. Negative values are
+    /// below the horizon.
+    pub min_pitch: f32,
+    /// The highest pitch, in radians, i.e., how far the camera can look up.
+    pub max_pitch: f32,
+    /// How long, in seconds, the camera takes to catch up with most of a mouse movement. Larger
+    /// values smooth out jerky mouse movement, at the cost of the camera lagging behind. Zero
+    /// turns the camera immediately.
+    pub mouse_smoothing: f32,
+    /// How much further fast mouse movements turn the camera than slow ones.
+    pub mouse_acceleration: AccelerationCurve,
+    /// The mouse button that grabs the cursor, so that moving the mouse turns the camera.
+    pub grab_cursor_button: MouseButton,
+    /// The key that releases the cursor.
+    pub release_cursor_key: KeyCode,
+}
+
+impl Default for FirstPersonCameraSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            mode: MovementMode::Fly,
+            move_speed: 9.0,
+            gravity: 9.81,
+            jump_speed: 4.0,
+            eye_height: 1.0,
+            ground_height: 0.0,
+            body_radius: 0.3,
+            mouse_sensitivity: 0.002,
+            invert_y: false,
+            min_pitch: -MAX_PITCH,
+            max_pitch: MAX_PITCH,
+            mouse_smoothing: 0.0,
+            mouse_acceleration: AccelerationCurve::Off,
+            grab_cursor_button: MouseButton::Left,
+            release_cursor_key: KeyCode::Escape,
+        }
+    }
+}
+
+/// A solid volume, upright and positioned at the translation of the entity's `Transform`, that
+/// cameras cannot move into. Rotation and scale are ignored.
+#[derive(Clone, Copy, Component, Debug)]
+pub enum Collider {
+    /// A vertical cylinder centered on the translation, like `shape::Cylinder`.
+    Cylinder { radius: f32, height: f32 },
+    /// A cone with its base centered on the translation and its apex `height` above it, like a
+    /// pyramid with many sides.
+    Cone { radius: f32, height: f32 },
+}
+
+impl Collider {
+    /// Returns how far the cylinder with its axis from `bottom` to `top` and the given `radius`
+    /// must be moved horizontally to no longer overlap this collider at `position`, or `None` if
+    /// they don't overlap.
+    fn push_out(self, position: Vec3, bottom: Vec3, top: f32, radius: f32) -> Option<Vec3> {
+        let (base, height) = match self {
+            Collider::Cylinder { height, .. } => (position.y - height / 2.0, height),
+            Collider::Cone { height, .. } => (position.y, height),
+        };
+        if top < base || bottom.y > base + height {
+            return None;
+        }
+
+        // The widest part of the collider at the heights the cylinder spans.
+        let widest = match self {
+            Collider::Cylinder { radius, .. } => radius,
+            Collider::Cone { radius, height } => {
+                radius * (1.0 - (bottom.y - base).max(0.0) / height)
+            }
+        };
+
+        let offset = (bottom - position).reject_from(Vec3::Y);
+        let distance = offset.length();
+        let min_distance = widest + radius;
+        if distance >= min_distance {
+            return None;
+        }
+
+        // Push the cylinder straight out, or in an arbitrary direction if it is exactly on the
+        // axis.
+        let direction = if distance > f32::EPSILON {
+            offset / distance
+        } else {
+            Vec3::X
+        };
+        Some(direction * (min_distance - distance))
+    }
+}
+
+/// Applied to a camera to control it with the mouse and keyboard. `yaw` is the angle the camera is
+/// turned to the left, and `pitch` the angle it is tilted up, both in radians. They are set from
+/// the camera's `Transform` when this component is added. The camera never rolls.
+#[derive(Clone, Component, Debug, Default)]
+pub struct FirstPersonCamera {
+    pub yaw: f32,
+    pub pitch: f32,
+    /// The mouse movement, in pixels per second, after smoothing.
+    smoothed_motion: Vec2,
+    /// The upward speed of the camera while walking. Negative while falling.
+    vertical_speed: f32,
+    /// Whether the camera is standing on the ground, so it can jump.
+    on_ground: bool,
+}
+
+impl FirstPersonCamera {
+    /// Returns the rotation of a camera facing in the direction given by `yaw` and `pitch`.
+    pub fn rotation(&self) -> Quat {
+        Quat::from_euler(EulerRot::YXZ, self.yaw, self.pitch, 0.)
+    }
+
+    /// Turns the camera by the mouse movement `delta`, in pixels, made over `seconds`, as
+    /// `settings` specifies. Keeps `pitch` within the limits in `settings`, and `yaw` within
+    /// -π..π.
+    pub fn turn(&mut self, delta: Vec2, seconds: f32, settings: &FirstPersonCameraSettings) {
+        if seconds <= 0.0 {
+            return;
+        }
+
+        let motion = delta / seconds;
+        let motion = motion * settings.mouse_acceleration.factor(motion.length());
+
+        self.smoothed_motion = if settings.mouse_smoothing > 0.0 {
+            let t = 1.0 - (-seconds / settings.mouse_smoothing).exp();
+            self.smoothed_motion.lerp(motion, t)
+        } else {
+            motion
+        };
+
+        let turn = self.smoothed_motion * seconds * settings.mouse_sensitivity;
+        let pitch_direction = if settings.invert_y { 1. } else { -1. };
+
+        self.yaw = (self.yaw - turn.x + PI).rem_euclid(TAU) - PI;
+        self.pitch =
+            (self.pitch + pitch_direction * turn.y).clamp(settings.min_pitch, settings.max_pitch);
+    }
+
+    /// Returns how far a camera with `rotation` moves over `seconds` while the movement keys give
+    /// the direction `movement`, as `settings` specifies. `jump` is whether the jump key was just
+    /// pressed. Walking cameras move with constant acceleration over the step, so they follow the
+    /// same path whatever the frame rate.
+    fn step(
+        &mut self,
+        rotation: Quat,
+        movement: Vec3,
+        jump: bool,
+        seconds: f32,
+        settings: &FirstPersonCameraSettings,
+    ) -> Vec3 {
+        match settings.mode {
+            MovementMode::Fly => rotation * movement * settings.move_speed * seconds,
+            MovementMode::Walk => {
+                let horizontal = Quat::from_rotation_y(self.yaw) * movement.reject_from(Vec3::Y);
+                if jump && self.on_ground {
+                    self.vertical_speed = settings.jump_speed;
+                }
+                let start_speed = self.vertical_speed;
+                self.vertical_speed -= settings.gravity * seconds;
+                let average_speed = (start_speed + self.vertical_speed) / 2.0;
+
+                (horizontal * settings.move_speed + Vec3::Y * average_speed) * seconds
+            }
+        }
+    }
+}
+
+/// Adds the `FirstPersonCameraSettings` resource and the systems that control cameras with a
+/// `FirstPersonCamera` component. Also adds `KeyBindingsPlugin` if it has not been added already.
+pub struct FirstPersonCameraPlugin;
+
+impl Plugin for FirstPersonCameraPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<KeyBindingsPlugin>() {
+            app.add_plugins(KeyBindingsPlugin);
+        }
+
+        app.init_resource::<FirstPersonCameraSettings>()
+            .add_systems(
+                Update,
+                (
+                    init_orientation,
+                    (
+                        grab_cursor,
+                        toggle_walking,
+                        turn_cameras,
+                        move_cameras,
+                        collide_cameras,
+                    )
+                        .run_if(rebinding_screen_closed),
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Sets the yaw and pitch of newly added `FirstPersonCamera` components to match the direction
+/// their camera already faces, so that the camera doesn't jump when it is first turned.
+fn init_orientation(
+    mut cameras: Query<(&mut FirstPersonCamera, &Transform), Added<FirstPersonCamera>>,
+    settings: Res<FirstPersonCameraSettings>,
+) {
+    for (mut camera, transform) in cameras.iter_mut() {
+        let (yaw, pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
+        camera.yaw = yaw;
+        camera.pitch = pitch.clamp(settings.min_pitch, settings.max_pitch);
+    }
+}
+
+/// Hides the mouse cursor and confines it to the window when `grab_cursor_button` is clicked, so
+/// that mouse movement turns the camera. Pressing `release_cursor_key`, or disabling the
+/// controller, shows the cursor again.
+fn grab_cursor(
+    mut primary_query: Query<&mut Window, With<PrimaryWindow>>,
+    btn: Res<Input<MouseButton>>,
+    key: Res<Input<KeyCode>>,
+    settings: Res<FirstPersonCameraSettings>,
+) {
+    let Ok(mut window) = primary_query.get_single_mut() else {
+        return;
+    };
+
+    if settings.enabled && btn.just_pressed(settings.grab_cursor_button) {
+        window.cursor.grab_mode = CursorGrabMode::Confined;
+        window.cursor.visible = false;
+    }
+
+    if key.just_pressed(settings.release_cursor_key) || (settings.is_changed() && !settings.enabled)
+    {
+        window.cursor.grab_mode = CursorGrabMode::None;
+        window.cursor.visible = true;
+    }
+}
+
+/// Switches between flying and walking when the key bound to `Action::ToggleWalking` is pressed.
+fn toggle_walking(
+    key: Res<Input<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut settings: ResMut<FirstPersonCameraSettings>,
+    mut cameras: Query<&mut FirstPersonCamera>,
+) {
+    if !settings.enabled || !bindings.just_pressed(Action::ToggleWalking, &key) {
+        return;
+    }
+
+    settings.mode = match settings.mode {
+        MovementMode::Fly => MovementMode::Walk,
+        MovementMode::Walk => MovementMode::Fly,
+    };
+    for mut camera in cameras.iter_mut() {
+        camera.vertical_speed = 0.0;
+        camera.on_ground = false;
+    }
+}
+
+/// Returns `true` if the cursor is grabbed, so the cameras should respond to the user.
+fn cursor_grabbed(primary_query: &Query<&Window, With<PrimaryWindow>>) -> bool {
+    primary_query
+        .get_single()
+        .is_ok_and(|window| !window.cursor.visible)
+}
+
+/// Turns the cameras in response to mouse movement while the cursor is grabbed. This runs every
+/// frame, even when the mouse doesn't move, so smoothed movement comes to a gradual stop.
+fn turn_cameras(
+    primary_query: Query<&Window, With<PrimaryWindow>>,
+    mut motion_evr: EventReader<MouseMotion>,
+    settings: Res<FirstPersonCameraSettings>,
+    time: Res<Time>,
+    mut cameras: Query<(&mut FirstPersonCamera, &mut Transform)>,
+) {
+    let delta: Vec2 = motion_evr.read().map(|event| event.delta).sum();
+    if !settings.enabled || !cursor_grabbed(&primary_query) {
+        return;
+    }
+
+    for (mut camera, mut transform) in cameras.iter_mut() {
+        camera.turn(delta, time.delta_seconds(), &settings);
+        transform.rotation = camera.rotation().normalize();
+    }
+}
+
+/// Moves the cameras while the keys bound to the movement actions are held and the cursor is
+/// grabbed. Flying cameras move relative to the direction they face. Walking cameras move
+/// horizontally relative to the direction they face, fall under gravity, and can jump while on the
+/// ground. Movement is scaled by the time since the previous frame so the speed doesn't depend on
+/// the frame rate.
+fn move_cameras(
+    primary_query: Query<&Window, With<PrimaryWindow>>,
+    key: Res<Input<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    settings: Res<FirstPersonCameraSettings>,
+    time: Res<Time>,
+    mut cameras: Query<(&mut FirstPersonCamera, &mut Transform)>,
+) {
+    if !settings.enabled {
+        return;
+    }
+
+    let grabbed = cursor_grabbed(&primary_query);
+    let mut movement = Vec3::ZERO;
+    if grabbed {
+        for (action, direction) in [
+            (Action::MoveForward, Vec3::NEG_Z),
+            (Action::MoveBackward, Vec3::Z),
+            (Action::StrafeLeft, Vec3::NEG_X),
+            (Action::StrafeRight, Vec3::X),
+            (Action::Up, Vec3::Y),
+            (Action::Down, Vec3::NEG_Y),
+        ] {
+            if bindings.pressed(action, &key) {
+                movement += direction;
+            }
+        }
+    }
+    let jump = grabbed && bindings.just_pressed(Action::Jump, &key);
+    let seconds = time.delta_seconds();
+
+    for (mut camera, mut transform) in cameras.iter_mut() {
+        let step = camera.step(transform.rotation, movement, jump, seconds, &settings);
+        transform.translation += step;
+    }
+}
+
+/// Pushes cameras out of any `Collider` they moved into, which leaves only the part of their
+/// movement along the collider's surface, so they slide around it. Then stands walking cameras on
+/// the ground rather than letting them fall through it.
+fn collide_cameras(
+    settings: Res<FirstPersonCameraSettings>,
+    terrains: Query<&Terrain>,
+    mut cameras: Query<(&mut FirstPersonCamera, &mut Transform), Without<Collider>>,
+    colliders: Query<(&Collider, &GlobalTransform)>,
+) {
+    if !settings.enabled {
+        return;
+    }
+
+    for (mut camera, mut transform) in cameras.iter_mut() {
+        for (collider, collider_transform) in colliders.iter() {
+            let bottom = transform.translation - Vec3::Y * settings.eye_height;
+            if let Some(push) = collider.push_out(
+                collider_transform.translation(),
+                bottom,
+                transform.translation.y,
+                settings.body_radius,
+            ) {
+                transform.translation += push;
+            }
+        }
+
+        if settings.mode == MovementMode::Walk {
+            let (x, z) = (transform.translation.x, transform.translation.z);
+            let ground = terrains
+                .iter()
+                .find(|terrain| terrain.contains(x, z))
+                .map_or(settings.ground_height, |terrain| terrain.height_at(x, z));
+            let eye_level = ground + settings.eye_height;
+            camera.on_ground = transform.translation.y <= eye_level;
+            if camera.on_ground {
+                transform.translation.y = eye_level;
+                camera.vertical_speed = 0.0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The frame rates the tests simulate, in frames per second.
+    const FRAME_RATES: [u32; 3] = [30, 60, 144];
+
+    /// Steps a camera for one second at `fps` frames per second, jumping on the first frame if
+    /// `jump` is set. Returns the camera's final position and the highest it reached.
+    fn simulate_second(
+        settings: &FirstPersonCameraSettings,
+        movement: Vec3,
+        jump: bool,
+        fps: u32,
+    ) -> (Vec3, f32) {
+        let mut camera = FirstPersonCamera {
+            on_ground: true,
+            ..Default::default()
+        };
+        let seconds = 1.0 / fps as f32;
+        let mut position = Vec3::ZERO;
+        let mut highest = 0.0f32;
+        for frame in 0..fps {
+            position += camera.step(
+                Quat::IDENTITY,
+                movement,
+                jump && frame == 0,
+                seconds,
+                settings,
+            );
+            camera.on_ground = false;
+            highest = highest.max(position.y);
+        }
+        (position, highest)
+    }
+
+    #[test]
+    fn flying_distance_does_not_depend_on_frame_rate() {
+        let settings = FirstPersonCameraSettings::default();
+        for fps in FRAME_RATES {
+            let (position, _) = simulate_second(&settings, Vec3::NEG_Z + Vec3::X, false, fps);
+            let expected = Vec3::new(1.0, 0.0, -1.0) * settings.move_speed;
+            assert!(
+                position.distance(expected) < 1e-3,
+                "flew to {} at {} fps, expected {}",
+                position,
+                fps,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn walking_distance_does_not_depend_on_frame_rate() {
+        let settings = FirstPersonCameraSettings {
+            mode: MovementMode::Walk,
+            ..Default::default()
+        };
+        for fps in FRAME_RATES {
+            let (position, _) = simulate_second(&settings, Vec3::NEG_Z, false, fps);
+            let fall = -settings.gravity / 2.0;
+            let expected = Vec3::new(0.0, fall, -settings.move_speed);
+            assert!(
+                position.distance(expected) < 1e-3,
+                "walked to {} at {} fps, expected {}",
+                position,
+                fps,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn jump_height_does_not_depend_on_frame_rate() {
+        let settings = FirstPersonCameraSettings {
+            mode: MovementMode::Walk,
+            ..Default::default()
+        };
+        let apex = settings.jump_speed * settings.jump_speed / (2.0 * settings.gravity);
+        let end = settings.jump_speed - settings.gravity / 2.0;
+        for fps in FRAME_RATES {
+            let (position, highest) = simulate_second(&settings, Vec3::ZERO, true, fps);
+            assert!(
+                (highest - apex).abs() < 1e-2,
+                "jumped {} high at {} fps, expected {}",
+                highest,
+                fps,
+                apex
+            );
+            assert!(
+                (position.y - end).abs() < 1e-3,
+                "ended at height {} at {} fps, expected {}",
+                position.y,
+                fps,
+                end
+            );
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! Procedural placement of trees. `generate_forest` scatters trees over an area using Poisson-disk
+//! sampling, so that no two trees are closer than a minimum spacing but they don't line up in a
+//! grid, and gives each tree random dimensions. The same seed always produces the same forest.
+//! `TreeMeshes` holds meshes that every tree can share, however many there are.
+use std::ops::Range;
+
+use bevy::prelude::*;
+
+use crate::pyramid::Pyramid;
+
+/// The number of candidate positions tried around each tree before giving up on finding space for
+/// another tree near it. Bridson's paper suggests 30.
+const CANDIDATES_PER_POINT: usize = 30;
+
+/// The number of sides of the pyramids used for the crowns of trees.
+pub const CROWN_SIDES: u32 = 8;
+
+/// A cube for tree trunks and a pyramid with `CROWN_SIDES` sides for crowns, both with sides and
+/// heights of one. Each tree scales them to its dimensions with its `Transform`, so any number of
+/// trees share these two meshes rather than each adding its own.
+#[derive(Clone, Debug, Resource)]
+pub struct TreeMeshes {
+    /// A cube centered on the origin.
+    pub trunk: Handle<Mesh>,
+    /// A pyramid with its base centered on the origin.
+    pub crown: Handle<Mesh>,
+}
+
+impl TreeMeshes {
+    /// Adds the shared meshes to `meshes`.
+    pub fn new(meshes: &mut Assets<Mesh>) -> Self {
+        Self {
+            trunk: meshes.add(Mesh::from(shape::Cube::new(1.0))),
+            crown: meshes.add(Mesh::from(Pyramid::new(CROWN_SIDES, 1.0, 1.0))),
+        }
+    }
+}
+
+impl FromWorld for TreeMeshes {
+    fn from_world(world: &mut World) -> Self {
+        TreeMeshes::new(&mut world.resource_mut::<Assets<Mesh>>())
+    }
+}
+
+/// Describes the forest to generate. Distances are in the XZ plane.
+#[derive(Clone, Debug)]
+pub struct ForestSettings {
+    /// The area trees are placed in, with `x` and `y` of the rectangle giving the X and Z
+    /// coordinates.
+    pub area: Rect,
+    /// The number of trees per unit of area. Fewer trees are placed if `min_spacing` doesn't leave
+    /// enough room.
+    pub density: f32,
+    /// The minimum distance between the centers of any two trunks.
+    pub min_spacing: f32,
+    /// The center and radius of a circle left free of trees, e.g., around the camera's starting
+    /// position.
+    pub clearing: (Vec2, f32),
+    pub trunk_height: Range<f32>,
+    pub trunk_width: Range<f32>,
+    pub crown_height: Range<f32>,
+    pub crown_width: Range<f32>,
+}
+
+impl Default for ForestSettings {
+    fn default() -> Self {
+        Self {
+            area: Rect::new(-50.0, -50.0, 50.0, 50.0),
+            density: 0.05,
+            min_spacing: 2.0,
+            clearing: (Vec2::ZERO, 3.0),
+            trunk_height: 0.8..1.4,
+            trunk_width: 0.2..0.35,
+            crown_height: 1.8..3.8,
+            crown_width: 0.5..1.0,
+        }
+    }
+}
+
+/// The dimensions and position of one generated tree, as passed to `tree_lod::spawn_tree`.
+/// `location` is the bottom of the trunk.
+#[derive(Clone, Copy, Debug)]
+pub struct Tree {
+    pub trunk_height: f32,
+    pub trunk_width: f32,
+    pub crown_height: f32,
+    pub crown_width: f32,
+    pub location: Vec3,
+}
+
+/// Returns the trees of a forest described by `settings`, with positions and dimensions chosen
+/// using `rng`. All trees are placed at a height of zero.
+pub fn generate_forest(settings: &ForestSettings, rng: &mut fastrand::Rng) -> Vec<Tree> {
+    let (clearing_center, clearing_radius) = settings.clearing;
+    let mut positions: Vec<Vec2> = poisson_disk(settings.area, settings.min_spacing, rng)
+        .into_iter()
+        .filter(|p| p.distance(clearing_center) >= clearing_radius)
+        .collect();
+
+    // Sampling packs trees as tightly as `min_spacing` allows, so thin them out to the density
+    // requested. Shuffling first removes trees evenly from across the area.
+    let area = settings.area.width() * settings.area.height();
+    rng.shuffle(&mut positions);
+    positions.truncate((area * settings.density).round() as usize);
+
+    positions
+        .into_iter()
+        .map(|p| Tree {
+            trunk_height: random_in(rng, &settings.trunk_height),
+            trunk_width: random_in(rng, &settings.trunk_width),
+            crown_height: random_in(rng, &settings.crown_height),
+            crown_width: random_in(rng, &settings.crown_width),
+            location: Vec3::new(p.x, 0.0, p.y),
+        })
+        .collect()
+}
+
+/// Returns a random value in `range`. Returns `range.start` if the range is empty.
+fn random_in(rng: &mut fastrand::Rng, range: &Range<f32>) -> f32 {
+    range.start + rng.f32() * (range.end - range.start).max(0.0)
+}
+
+/// Returns points filling `area` such that no two are closer than `min_spacing`, using Bridson's
+/// algorithm. A grid with cells small enough to hold at most one point makes checking for nearby
+/// points quick.
+fn poisson_disk(area: Rect, min_spacing: f32, rng: &mut fastrand::Rng) -> Vec<Vec2> {
+    if area.is_empty() || min_spacing <= 0.0 {
+        return Vec::new();
+    }
+
+    let cell_size = min_spacing / std::f32::consts::SQRT_2;
+    let columns = (area.width() / cell_size).ceil() as usize;
+    let rows = (area.height() / cell_size).ceil() as usize;
+    let mut grid: Vec<Option<usize>> = vec![None; columns * rows];
+    let cell_of = |p: Vec2| {
+        let cell = ((p - area.min) / cell_size).as_uvec2();
+        (
+            (cell.x as usize).min(columns - 1),
+            (cell.y as usize).min(rows - 1),
+        )
+    };
+
+    let first = area.min + Vec2::new(rng.f32(), rng.f32()) * area.size();
+    let mut points = vec![first];
+    let (column, row) = cell_of(first);
+    grid[row * columns + column] = Some(0);
+    let mut active = vec![0];
+
+    while !active.is_empty() {
+        let active_index = rng.usize(0..active.len());
+        let center = points[active[active_index]];
+
+        // Try random points in the ring between one and two times `min_spacing` from `center`.
+        let candidate = (0..CANDIDATES_PER_POINT)
+            .map(|_| {
+                let angle = rng.f32() * std::f32::consts::TAU;
+                let distance = min_spacing * (1.0 + rng.f32());
+                center + Vec2::from_angle(angle) * distance
+            })
+            .find(|&candidate| {
+                if !area.contains(candidate) {
+                    return false;
+                }
+                let (column, row) = cell_of(candidate);
+                let neighbours = row.saturating_sub(2)..(row + 3).min(rows);
+                neighbours.into_iter().all(|r| {
+                    (column.saturating_sub(2)..(column + 3).min(columns)).all(|c| {
+                        grid[r * columns + c]
+                            .is_none_or(|i| points[i].distance(candidate) >= min_spacing)
+                    })
+                })
+            });
+
+        match candidate {
+            Some(candidate) => {
+                let (column, row) = cell_of(candidate);
+                grid[row * columns + column] = Some(points.len());
+                active.push(points.len());
+                points.push(candidate);
+            }
+            None => {
+                active.swap_remove(active_index);
+            }
+        }
+    }
+
+    points
+}
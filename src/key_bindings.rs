@@ -0,0 +1,443 @@
+//! Key bindings for the demos that move a camera around a scene. Each `Action` is bound to one key,
+//! starting from the keys of a `Preset` and then applying any changes saved in
+//! `SETTINGS_FILENAME`. Pressing `REBINDING_SCREEN_KEY` opens a screen listing the bindings, where
+//! clicking an action and then pressing a key binds the action to that key. Changes are saved so
+//! they apply to future runs of every demo that uses these bindings.
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use bevy::reflect::{DynamicEnum, DynamicVariant};
+use bevy::window::{CursorGrabMode, PrimaryWindow};
+
+use crate::settings;
+
+/// The name of the settings file holding the key bindings.
+pub const SETTINGS_FILENAME: &str = "key-bindings.cfg";
+
+/// The key that opens and closes the rebinding screen. It cannot be bound to an action.
+pub const REBINDING_SCREEN_KEY: KeyCode = KeyCode::F1;
+
+/// The key that cancels rebinding an action. It cannot be bound to an action.
+const CANCEL_KEY: KeyCode = KeyCode::Escape;
+
+const PRESET_SETTING: &str = "preset";
+const FONT_ASSET_FILENAME: &str = "fonts/FiraSans-Bold.ttf";
+const TEXT_COLOR: Color = Color::rgb(0.6, 0.6, 1.0);
+const BACKGROUND_COLOR: Color = Color::rgba(0.0, 0.0, 0.0, 0.8);
+const BUTTON_COLOR: Color = Color::rgb(0.2, 0.2, 0.35);
+const HOVERED_BUTTON_COLOR: Color = Color::rgb(0.3, 0.3, 0.5);
+
+/// The things the user can do by pressing a key.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    StrafeLeft,
+    StrafeRight,
+    Up,
+    Down,
+    Jump,
+    ToggleWalking,
+}
+
+impl Action {
+    pub const ALL: [Action; 8] = [
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::StrafeLeft,
+        Action::StrafeRight,
+        Action::Up,
+        Action::Down,
+        Action::Jump,
+        Action::ToggleWalking,
+    ];
+
+    /// Returns the name used for this action in the settings file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveForward => "move-forward",
+            Action::MoveBackward => "move-backward",
+            Action::StrafeLeft => "strafe-left",
+            Action::StrafeRight => "strafe-right",
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::Jump => "jump",
+            Action::ToggleWalking => "toggle-walking",
+        }
+    }
+
+    /// Returns the action with the given name, or `None` if there isn't one.
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|a| a.name() == name)
+    }
+
+    /// Returns the description of this action shown on the rebinding screen.
+    fn label(self) -> &'static str {
+        match self {
+            Action::MoveForward => "Move forward",
+            Action::MoveBackward => "Move backward",
+            Action::StrafeLeft => "Strafe left",
+            Action::StrafeRight => "Strafe right",
+            Action::Up => "Up",
+            Action::Down => "Down",
+            Action::Jump => "Jump",
+            Action::ToggleWalking => "Walk / fly",
+        }
+    }
+}
+
+/// Sets of bindings suited to different keyboard layouts. Both put the movement keys in the same
+/// place on the keyboard.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Preset {
+    Qwerty,
+    #[default]
+    Dvorak,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 2] = [Preset::Qwerty, Preset::Dvorak];
+
+    /// Returns the name used for this preset in the settings file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::Qwerty => "qwerty",
+            Preset::Dvorak => "dvorak",
+        }
+    }
+
+    /// Returns the preset with the given name, ignoring case, or `None` if there isn't one.
+    pub fn from_name(name: &str) -> Option<Preset> {
+        Preset::ALL
+            .into_iter()
+            .find(|p| p.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Returns the preset after this one, wrapping around after the last.
+    fn next(self) -> Preset {
+        let index = Preset::ALL.iter().position(|&p| p == self).unwrap();
+        Preset::ALL[(index + 1) % Preset::ALL.len()]
+    }
+
+    /// Returns the key this preset binds to `action`.
+    pub fn key(self, action: Action) -> KeyCode {
+        match (self, action) {
+            (Preset::Qwerty, Action::MoveForward) => KeyCode::W,
+            (Preset::Qwerty, Action::MoveBackward) => KeyCode::S,
+            (Preset::Qwerty, Action::StrafeLeft) => KeyCode::A,
+            (Preset::Qwerty, Action::StrafeRight) => KeyCode::D,
+            (Preset::Qwerty, Action::Up) => KeyCode::ShiftLeft,
+            (Preset::Qwerty, Action::Down) => KeyCode::C,
+            (Preset::Qwerty, Action::Jump) => KeyCode::Space,
+            (Preset::Qwerty, Action::ToggleWalking) => KeyCode::F,
+            (Preset::Dvorak, Action::MoveForward) => KeyCode::Comma,
+            (Preset::Dvorak, Action::MoveBackward) => KeyCode::O,
+            (Preset::Dvorak, Action::StrafeLeft) => KeyCode::A,
+            (Preset::Dvorak, Action::StrafeRight) => KeyCode::E,
+            (Preset::Dvorak, Action::Up) => KeyCode::ShiftLeft,
+            (Preset::Dvorak, Action::Down) => KeyCode::J,
+            (Preset::Dvorak, Action::Jump) => KeyCode::Space,
+            (Preset::Dvorak, Action::ToggleWalking) => KeyCode::U,
+        }
+    }
+}
+
+/// The key bound to each action.
+#[derive(Clone, Debug, Resource)]
+pub struct KeyBindings {
+    preset: Preset,
+    keys: BTreeMap<Action, KeyCode>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings::from_preset(Preset::default())
+    }
+}
+
+impl KeyBindings {
+    /// Returns the bindings of `preset`.
+    pub fn from_preset(preset: Preset) -> Self {
+        Self {
+            preset,
+            keys: Action::ALL.iter().map(|&a| (a, preset.key(a))).collect(),
+        }
+    }
+
+    /// Returns the bindings saved in `SETTINGS_FILENAME`, i.e., those of the saved preset with any
+    /// saved changes applied. Returns the default bindings if none have been saved.
+    pub fn load() -> Self {
+        let saved = settings::load_settings(SETTINGS_FILENAME);
+
+        let preset = saved
+            .get(PRESET_SETTING)
+            .and_then(|name| Preset::from_name(name))
+            .unwrap_or_default();
+        let mut bindings = KeyBindings::from_preset(preset);
+
+        for (name, key_name) in saved.iter().filter(|(name, _)| *name != PRESET_SETTING) {
+            match (Action::from_name(name), key_from_name(key_name)) {
+                (Some(action), Some(key)) => bindings.bind(action, key),
+                _ => warn!("Ignoring key binding {} = {}", name, key_name),
+            }
+        }
+        bindings
+    }
+
+    /// Saves these bindings to `SETTINGS_FILENAME`. Logs a warning if they cannot be saved.
+    pub fn save(&self) {
+        let mut saved = BTreeMap::new();
+        saved.insert(PRESET_SETTING.to_string(), self.preset.name().to_string());
+        for (action, key) in self.keys.iter() {
+            saved.insert(action.name().to_string(), format!("{:?}", key));
+        }
+
+        if let Err(e) = settings::save_settings(SETTINGS_FILENAME, &saved) {
+            warn!("Cannot save key bindings: {}", e);
+        }
+    }
+
+    /// Returns the preset these bindings started from.
+    pub fn preset(&self) -> Preset {
+        self.preset
+    }
+
+    /// Returns the key bound to `action`.
+    pub fn key(&self, action: Action) -> KeyCode {
+        self.keys[&action]
+    }
+
+    /// Binds `action` to `key`. Any other action bound to `key` is given `action`'s old key, so
+    /// that every action can still be performed.
+    pub fn bind(&mut self, action: Action, key: KeyCode) {
+        let old_key = self.key(action);
+        for bound in self.keys.values_mut().filter(|bound| **bound == key) {
+            *bound = old_key;
+        }
+        self.keys.insert(action, key);
+    }
+
+    /// Returns `true` if the key bound to `action` is being pressed.
+    pub fn pressed(&self, action: Action, input: &Input<KeyCode>) -> bool {
+        input.pressed(self.key(action))
+    }
+
+    /// Returns `true` if the key bound to `action` was pressed since the last frame.
+    pub fn just_pressed(&self, action: Action, input: &Input<KeyCode>) -> bool {
+        input.just_pressed(self.key(action))
+    }
+}
+
+/// Returns the key with the given name, as written by `KeyBindings::save`, e.g., "W" or
+/// "ShiftLeft". Returns `None` if there is no such key.
+fn key_from_name(name: &str) -> Option<KeyCode> {
+    KeyCode::from_reflect(&DynamicEnum::new(name, DynamicVariant::Unit))
+}
+
+/// Whether the rebinding screen is shown, and the action being rebound, if any.
+#[derive(Default, Resource)]
+pub struct RebindingScreen {
+    open: bool,
+    waiting_for_key: Option<Action>,
+}
+
+impl RebindingScreen {
+    /// Returns `true` if the rebinding screen is shown.
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+}
+
+/// A run condition that is `true` while the rebinding screen is not shown, so demos can stop
+/// responding to input while it is. It is also `true` if `KeyBindingsPlugin` hasn't been added, so
+/// plugins that don't need key bindings can use it too.
+pub fn rebinding_screen_closed(screen: Option<Res<RebindingScreen>>) -> bool {
+    screen.is_none_or(|screen| !screen.open)
+}
+
+/// A label applied to the root node of the rebinding screen, so it can be removed.
+#[derive(Component)]
+struct RebindingScreenRoot;
+
+/// The buttons on the rebinding screen.
+#[derive(Clone, Copy, Component)]
+enum RebindingButton {
+    Preset,
+    Action(Action),
+}
+
+/// Adds the `KeyBindings` resource, loaded from `SETTINGS_FILENAME`, and the rebinding screen.
+pub struct KeyBindingsPlugin;
+
+impl Plugin for KeyBindingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(KeyBindings::load())
+            .init_resource::<RebindingScreen>()
+            .add_systems(
+                Update,
+                (
+                    capture_key,
+                    toggle_rebinding_screen,
+                    rebinding_screen_buttons,
+                    redraw_rebinding_screen,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Opens or closes the rebinding screen when `REBINDING_SCREEN_KEY` is pressed. Opening it
+/// releases the mouse cursor so the buttons can be clicked.
+fn toggle_rebinding_screen(
+    key: Res<Input<KeyCode>>,
+    mut screen: ResMut<RebindingScreen>,
+    mut primary_query: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    if !key.just_pressed(REBINDING_SCREEN_KEY) {
+        return;
+    }
+
+    screen.open = !screen.open;
+    screen.waiting_for_key = None;
+
+    if screen.open {
+        if let Ok(mut window) = primary_query.get_single_mut() {
+            window.cursor.grab_mode = CursorGrabMode::None;
+            window.cursor.visible = true;
+        }
+    }
+}
+
+/// Binds the action being rebound to the next key pressed, unless it is `CANCEL_KEY` or
+/// `REBINDING_SCREEN_KEY`, which cancel rebinding.
+fn capture_key(
+    key: Res<Input<KeyCode>>,
+    mut screen: ResMut<RebindingScreen>,
+    mut bindings: ResMut<KeyBindings>,
+) {
+    let Some(action) = screen.waiting_for_key else {
+        return;
+    };
+    let Some(&pressed) = key.get_just_pressed().next() else {
+        return;
+    };
+
+    screen.waiting_for_key = None;
+    if pressed != CANCEL_KEY && pressed != REBINDING_SCREEN_KEY {
+        bindings.bind(action, pressed);
+        bindings.save();
+    }
+}
+
+/// Highlights the button under the mouse pointer, and responds to clicks. Clicking an action waits
+/// for the key to bind it to, and clicking the preset switches to the next preset's bindings.
+fn rebinding_screen_buttons(
+    mut buttons: Query<
+        (&Interaction, &RebindingButton, &mut BackgroundColor),
+        Changed<Interaction>,
+    >,
+    mut screen: ResMut<RebindingScreen>,
+    mut bindings: ResMut<KeyBindings>,
+) {
+    for (interaction, button, mut color) in buttons.iter_mut() {
+        match interaction {
+            Interaction::Pressed => match button {
+                RebindingButton::Preset => {
+                    *bindings = KeyBindings::from_preset(bindings.preset().next());
+                    bindings.save();
+                }
+                RebindingButton::Action(action) => screen.waiting_for_key = Some(*action),
+            },
+            Interaction::Hovered => *color = HOVERED_BUTTON_COLOR.into(),
+            Interaction::None => *color = BUTTON_COLOR.into(),
+        }
+    }
+}
+
+/// Rebuilds the rebinding screen whenever it is opened or closed, or a binding changes.
+fn redraw_rebinding_screen(
+    mut commands: Commands,
+    screen: Res<RebindingScreen>,
+    bindings: Res<KeyBindings>,
+    asset_server: Res<AssetServer>,
+    roots: Query<Entity, With<RebindingScreenRoot>>,
+) {
+    if !screen.is_changed() && !bindings.is_changed() {
+        return;
+    }
+
+    for entity in roots.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    if !screen.open {
+        return;
+    }
+
+    let text_style = |font_size| TextStyle {
+        font: asset_server.load(FONT_ASSET_FILENAME),
+        font_size,
+        color: TEXT_COLOR,
+    };
+
+    let mut labels = vec![(
+        RebindingButton::Preset,
+        format!("Preset: {}", bindings.preset().name()),
+    )];
+    for action in Action::ALL {
+        let key = match screen.waiting_for_key {
+            Some(waiting) if waiting == action => "press a key".to_string(),
+            _ => format!("{:?}", bindings.key(action)),
+        };
+        labels.push((
+            RebindingButton::Action(action),
+            format!("{}: {}", action.label(), key),
+        ));
+    }
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                row_gap: Val::Px(10.0),
+                ..Default::default()
+            },
+            background_color: BACKGROUND_COLOR.into(),
+            ..Default::default()
+        })
+        .insert(RebindingScreenRoot)
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section("Key bindings", text_style(50.0)));
+
+            for (button, label) in labels {
+                parent
+                    .spawn(ButtonBundle {
+                        style: Style {
+                            width: Val::Px(360.0),
+                            height: Val::Px(40.0),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        background_color: BUTTON_COLOR.into(),
+                        ..Default::default()
+                    })
+                    .insert(button)
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(label, text_style(24.0)));
+                    });
+            }
+
+            parent.spawn(TextBundle::from_section(
+                format!(
+                    "Click an action, then press its new key. Press {:?} to close.",
+                    REBINDING_SCREEN_KEY
+                ),
+                text_style(20.0),
+            ));
+        });
+}
@@ -0,0 +1,13 @@
+//! Code shared between the demos in `src/bin`.
+pub mod day_night;
+pub mod first_person_camera;
+pub mod forest;
+pub mod key_bindings;
+pub mod localization;
+pub mod pyramid;
+pub mod rng;
+pub mod scene_description;
+pub mod settings;
+pub mod sky;
+pub mod terrain;
+pub mod tree_lod;
@@ -0,0 +1,135 @@
+//! Tables of the text shown to users, so demos can be translated into other languages. Each demo
+//! has a directory under `assets/locales` holding one file per language, named after the
+//! language's two letter code, e.g., `en.txt` for English. Files hold one `key = value` pair per
+//! line. Text missing from a language's file is taken from the English file instead.
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use bevy::asset::io::file::FileAssetReader;
+use bevy::prelude::*;
+
+use crate::settings;
+
+/// The language used when no other is chosen, and for any text missing from another language.
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+/// The text for one demo in the chosen language, with English as a fallback.
+#[derive(Resource)]
+pub struct StringTable {
+    demo: String,
+    language: String,
+    strings: BTreeMap<String, String>,
+    fallback: BTreeMap<String, String>,
+}
+
+impl StringTable {
+    /// Loads the text for `demo` in `language`. If `language` has no file, English is used.
+    pub fn load(demo: &str, language: &str) -> Self {
+        let fallback = load_language_file(demo, DEFAULT_LANGUAGE);
+        let strings = if language == DEFAULT_LANGUAGE {
+            BTreeMap::new()
+        } else {
+            load_language_file(demo, language)
+        };
+
+        let language = if strings.is_empty() {
+            DEFAULT_LANGUAGE
+        } else {
+            language
+        };
+
+        Self {
+            demo: demo.to_string(),
+            language: language.to_string(),
+            strings,
+            fallback,
+        }
+    }
+
+    /// Returns the code of the language in use, e.g., "en".
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    /// Returns the codes of every language that `demo` has text for, in alphabetical order.
+    pub fn available_languages(&self) -> Vec<String> {
+        let Ok(entries) = fs::read_dir(locale_dir(&self.demo)) else {
+            return vec![DEFAULT_LANGUAGE.to_string()];
+        };
+
+        let mut languages: Vec<String> = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != "txt" {
+                    return None;
+                }
+                Some(path.file_stem()?.to_str()?.to_string())
+            })
+            .filter(|code| is_language_code(code))
+            .collect();
+        languages.sort();
+        languages
+    }
+
+    /// Returns the text for `key`. If there is none in the language in use, the English text is
+    /// returned, or failing that, `key` itself so the missing text is easy to spot.
+    pub fn get<'a>(&'a self, key: &'a str) -> &'a str {
+        self.strings
+            .get(key)
+            .or_else(|| self.fallback.get(key))
+            .map(String::as_str)
+            .unwrap_or(key)
+    }
+
+    /// Returns the text for `key` as `get` does, with each `{0}`, `{1}`, etc. replaced by the
+    /// corresponding entry in `args`.
+    pub fn format(&self, key: &str, args: &[&dyn std::fmt::Display]) -> String {
+        let mut text = self.get(key).to_string();
+        for (i, arg) in args.iter().enumerate() {
+            text = text.replace(&format!("{{{}}}", i), &arg.to_string());
+        }
+        text
+    }
+}
+
+/// Returns whether `code` is a two letter language code in lower case, e.g., "fr". Only such codes
+/// are used to name language files, so that a code can't name a file outside the locale directory.
+pub fn is_language_code(code: &str) -> bool {
+    code.len() == 2 && code.chars().all(|c| c.is_ascii_lowercase())
+}
+
+/// Returns the two letter code of the language set in the system locale, e.g., "fr" for a locale
+/// of "fr_FR.UTF-8". Returns `None` if no locale is set, or it is the "C" or "POSIX" locale.
+pub fn system_language() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|locale| !locale.is_empty())
+        .map(|locale| {
+            let end = locale.find(['_', '.', '@']).unwrap_or(locale.len());
+            locale[..end].to_lowercase()
+        })
+        .filter(|language| is_language_code(language))
+}
+
+/// Returns the directory holding the language files for `demo`.
+fn locale_dir(demo: &str) -> PathBuf {
+    FileAssetReader::get_base_path()
+        .join("assets")
+        .join("locales")
+        .join(demo)
+}
+
+/// Returns the text in the file for `demo` in `language`, or no text if there is no such file.
+fn load_language_file(demo: &str, language: &str) -> BTreeMap<String, String> {
+    let path = locale_dir(demo).join(format!("{}.txt", language));
+
+    match fs::read_to_string(&path) {
+        Ok(contents) => settings::parse_key_values(&contents, &path.display().to_string()),
+        Err(e) => {
+            warn!("Cannot read text from {}: {}", path.display(), e);
+            BTreeMap::new()
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! A seedable random number generator, so that runs of a demo that rely on randomness can be
+//! reproduced exactly. The seed is taken from the `--seed <N>` command line option if present,
+//! otherwise from the `SEED_ENV_VAR` environment variable, otherwise it is chosen at random. The
+//! seed is logged at startup so that it can be included in bug reports.
+use std::fmt;
+use std::path::Path;
+
+use bevy::prelude::*;
+
+/// The environment variable checked for a seed if none is given on the command line.
+pub const SEED_ENV_VAR: &str = "BEVY_DEMOS_SEED";
+
+/// The command line option used to pass a seed.
+pub const SEED_OPTION: &str = "--seed";
+
+/// The random number generator shared by all systems in a demo. It dereferences to a
+/// `fastrand::Rng`.
+#[derive(Resource, Deref, DerefMut)]
+pub struct SeededRng {
+    #[deref]
+    rng: fastrand::Rng,
+    seed: u64,
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: fastrand::Rng::with_seed(seed),
+            seed,
+        }
+    }
+
+    /// Returns the seed this generator was created with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+/// Adds a `SeededRng` resource to the app, seeded by `choose_seed_or_exit`, and logs the seed.
+pub struct SeededRngPlugin;
+
+impl Plugin for SeededRngPlugin {
+    fn build(&self, app: &mut App) {
+        let seed = choose_seed_or_exit();
+        info!(
+            "Random seed is {}. Pass '{} {}' or set {}={} to replay this run.",
+            seed, SEED_OPTION, seed, SEED_ENV_VAR, seed
+        );
+        app.insert_resource(SeededRng::new(seed));
+    }
+}
+
+/// A seed given on the command line or in `SEED_ENV_VAR` that is not a valid `u64`.
+#[derive(Debug)]
+pub struct InvalidSeedError(String);
+
+impl fmt::Display for InvalidSeedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid random seed '{}', which must be a whole number from 0 to {}",
+            self.0,
+            u64::MAX
+        )
+    }
+}
+
+impl std::error::Error for InvalidSeedError {}
+
+/// Returns the seed passed on the command line, or in `SEED_ENV_VAR`, or a random seed if neither
+/// is set. Returns an error if the seed given is not a valid `u64`.
+pub fn choose_seed() -> Result<u64, InvalidSeedError> {
+    let mut args = std::env::args().skip_while(|arg| arg != SEED_OPTION);
+    let given = args.nth(1).or_else(|| std::env::var(SEED_ENV_VAR).ok());
+
+    match given {
+        Some(s) => s.parse().map_err(|_| InvalidSeedError(s)),
+        None => Ok(fastrand::u64(..)),
+    }
+}
+
+/// Returns the seed chosen by `choose_seed`. If the seed given is invalid, prints the error and
+/// exits with status 1, as the demos do for other invalid options.
+pub fn choose_seed_or_exit() -> u64 {
+    choose_seed().unwrap_or_else(|e| {
+        let program = std::env::args()
+            .next()
+            .and_then(|path| {
+                Path::new(&path)
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+            })
+            .unwrap_or_default();
+        eprintln!("{}: {}", program, e);
+        std::process::exit(1);
+    })
+}
+
+/// Mixes the bits of `value` so that similar values give unrelated results. This is the finalizer
+/// of the SplitMix64 generator.
+pub fn hash(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,452 @@
+//! Scenes described in RON files rather than in code. A `SceneDescription` lists materials,
+//! objects, forests, lights and a camera. It is loaded as an asset from a `.scene.ron` file, and
+//! spawned as the children of an entity with a `DescribedScene` component. When the file changes
+//! the scene is spawned again, so it can be edited while a demo is running. See
+//! `assets/scenes/cube.scene.ron` for an example of the format.
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, AsyncReadExt, LoadContext};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+
+use crate::first_person_camera::FirstPersonCamera;
+use crate::forest::{generate_forest, ForestSettings, TreeMeshes};
+use crate::pyramid::Pyramid;
+use crate::rng::{hash, SeededRng};
+use crate::tree_lod::{spawn_tree, TreeOptions};
+
+/// The contents of a scene description file.
+#[derive(Asset, Clone, Debug, Deserialize, TypePath)]
+pub struct SceneDescription {
+    /// Materials, by the names objects and forests refer to them by.
+    #[serde(default)]
+    pub materials: HashMap<String, MaterialDescription>,
+    #[serde(default)]
+    pub objects: Vec<ObjectDescription>,
+    #[serde(default)]
+    pub forests: Vec<ForestDescription>,
+    #[serde(default)]
+    pub lights: Vec<LightDescription>,
+    /// The position and orientation of the camera, which is controlled by `FirstPersonCamera`.
+    /// There is no camera if this is omitted.
+    #[serde(default)]
+    pub camera: Option<TransformDescription>,
+}
+
+/// A material, given by its color in sRGB.
+#[derive(Clone, Debug, Deserialize)]
+pub struct MaterialDescription {
+    pub color: [f32; 3],
+}
+
+/// A transform. Rotations are in degrees about the X, Y and Z axes, applied in that order. If
+/// `looking_at` is given, it replaces the rotation by one that points -Z at that position.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct TransformDescription {
+    pub translation: [f32; 3],
+    pub rotation: [f32; 3],
+    pub scale: [f32; 3],
+    pub looking_at: Option<[f32; 3]>,
+}
+
+impl Default for TransformDescription {
+    fn default() -> Self {
+        Self {
+            translation: [0.0; 3],
+            rotation: [0.0; 3],
+            scale: [1.0; 3],
+            looking_at: None,
+        }
+    }
+}
+
+impl From<&TransformDescription> for Transform {
+    fn from(description: &TransformDescription) -> Self {
+        let [x, y, z] = description.rotation.map(f32::to_radians);
+        let transform = Transform {
+            translation: Vec3::from(description.translation),
+            rotation: Quat::from_euler(EulerRot::XYZ, x, y, z),
+            scale: Vec3::from(description.scale),
+        };
+
+        match description.looking_at {
+            Some(target) => transform.looking_at(Vec3::from(target), Vec3::Y),
+            None => transform,
+        }
+    }
+}
+
+/// The shape of an object.
+#[derive(Clone, Debug, Deserialize)]
+pub enum MeshDescription {
+    Cube {
+        size: f32,
+    },
+    Box {
+        x: f32,
+        y: f32,
+        z: f32,
+    },
+    Pyramid {
+        sides: u32,
+        side_length: f32,
+        height: f32,
+    },
+    Plane {
+        size: f32,
+    },
+    Sphere {
+        radius: f32,
+    },
+}
+
+impl From<&MeshDescription> for Mesh {
+    fn from(description: &MeshDescription) -> Self {
+        match *description {
+            MeshDescription::Cube { size } => Mesh::from(shape::Cube::new(size)),
+            MeshDescription::Box { x, y, z } => Mesh::from(shape::Box::new(x, y, z)),
+            MeshDescription::Pyramid {
+                sides,
+                side_length,
+                height,
+            } => Mesh::from(Pyramid::new(sides.max(3), side_length, height)),
+            MeshDescription::Plane { size } => Mesh::from(shape::Plane::from_size(size)),
+            MeshDescription::Sphere { radius } => Mesh::from(shape::UVSphere {
+                radius,
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+/// An object with a shape and a material.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ObjectDescription {
+    pub mesh: MeshDescription,
+    /// The name of one of the scene's materials.
+    pub material: String,
+    #[serde(default)]
+    pub transform: TransformDescription,
+    /// The speed the object turns about its Y axis, in radians per second.
+    #[serde(default)]
+    pub spin: f32,
+}
+
+/// A forest generated by `generate_forest`. Settings that are omitted take their default values.
+/// The forest is the same each time the scene is spawned during a run.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ForestDescription {
+    /// The area covered, as the minimum X and Z followed by the maximum X and Z.
+    pub area: [f32; 4],
+    #[serde(default)]
+    pub density: Option<f32>,
+    #[serde(default)]
+    pub min_spacing: Option<f32>,
+    /// The center, in X and Z, and radius of an area left free of trees.
+    #[serde(default)]
+    pub clearing: Option<([f32; 2], f32)>,
+    /// The names of the scene's materials for tree crowns and trunks.
+    pub crown_material: String,
+    pub trunk_material: String,
+}
+
+impl From<&ForestDescription> for ForestSettings {
+    fn from(description: &ForestDescription) -> Self {
+        let defaults = ForestSettings::default();
+        let [min_x, min_z, max_x, max_z] = description.area;
+        Self {
+            area: Rect::new(min_x, min_z, max_x, max_z),
+            density: description.density.unwrap_or(defaults.density),
+            min_spacing: description.min_spacing.unwrap_or(defaults.min_spacing),
+            clearing: description
+                .clearing
+                .map_or(defaults.clearing, |(center, radius)| {
+                    (Vec2::from(center), radius)
+                }),
+            ..defaults
+        }
+    }
+}
+
+/// A light. Values that are omitted take Bevy's defaults.
+#[derive(Clone, Debug, Deserialize)]
+pub enum LightDescription {
+    Point {
+        #[serde(default)]
+        transform: TransformDescription,
+        #[serde(default)]
+        intensity: Option<f32>,
+        #[serde(default)]
+        shadows: bool,
+    },
+    Directional {
+        #[serde(default)]
+        transform: TransformDescription,
+        #[serde(default)]
+        illuminance: Option<f32>,
+        #[serde(default)]
+        shadows: bool,
+    },
+}
+
+/// The ways loading a scene description can fail.
+#[derive(Debug)]
+pub enum SceneDescriptionError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl fmt::Display for SceneDescriptionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "cannot read scene description: {}", e),
+            Self::Ron(e) => write!(f, "invalid scene description: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SceneDescriptionError {}
+
+impl From<std::io::Error> for SceneDescriptionError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<ron::error::SpannedError> for SceneDescriptionError {
+    fn from(e: ron::error::SpannedError) -> Self {
+        Self::Ron(e)
+    }
+}
+
+/// Loads `SceneDescription`s from `.scene.ron` files.
+#[derive(Default)]
+struct SceneDescriptionLoader;
+
+impl AssetLoader for SceneDescriptionLoader {
+    type Asset = SceneDescription;
+    type Settings = ();
+    type Error = SceneDescriptionError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<SceneDescription, SceneDescriptionError>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            Ok(ron::de::from_bytes(&bytes)?)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["scene.ron"]
+    }
+}
+
+/// Spawns the scene described by `handle` as the children of this entity, once it has loaded and
+/// again whenever it changes. The camera is spawned only the first time, so that reloading the
+/// scene doesn't move the view.
+#[derive(Component)]
+pub struct DescribedScene {
+    pub handle: Handle<SceneDescription>,
+    camera: Option<Entity>,
+}
+
+impl DescribedScene {
+    pub fn new(handle: Handle<SceneDescription>) -> Self {
+        Self {
+            handle,
+            camera: None,
+        }
+    }
+}
+
+/// Turns an object about its Y axis, from the rotation it was spawned with.
+#[derive(Component)]
+struct Spin {
+    speed: f32,
+    rotation: Quat,
+}
+
+/// Adds the `SceneDescription` asset and its loader, and the systems that spawn described scenes
+/// and make objects spin. Add `TreeLodPlugin` too if any scene contains a forest.
+pub struct SceneDescriptionPlugin;
+
+impl Plugin for SceneDescriptionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<SceneDescription>()
+            .init_asset_loader::<SceneDescriptionLoader>()
+            .init_resource::<TreeMeshes>()
+            .add_systems(Update, (spawn_described_scenes, spin_objects));
+    }
+}
+
+/// The assets used to spawn a scene.
+#[derive(SystemParam)]
+struct SceneAssets<'w> {
+    meshes: ResMut<'w, Assets<Mesh>>,
+    materials: ResMut<'w, Assets<StandardMaterial>>,
+    tree_meshes: Res<'w, TreeMeshes>,
+}
+
+/// Spawns the children of `DescribedScene` entities that are new, or whose description has loaded
+/// or changed, replacing any children spawned before.
+fn spawn_described_scenes(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<SceneDescription>>,
+    descriptions: Res<Assets<SceneDescription>>,
+    mut roots: Query<(Entity, &mut DescribedScene)>,
+    mut assets: SceneAssets,
+    rng: Option<Res<SeededRng>>,
+) {
+    // A reload can send more than one event for the same asset, so each is only handled once.
+    let changed: HashSet<AssetId<SceneDescription>> = events
+        .read()
+        .filter_map(|event| match *event {
+            AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } => Some(id),
+            _ => None,
+        })
+        .collect();
+    let seed = rng.map_or(0, |rng| rng.seed());
+
+    for (root, mut scene) in roots.iter_mut() {
+        if !scene.is_added() && !changed.contains(&scene.handle.id()) {
+            continue;
+        }
+        let Some(description) = descriptions.get(&scene.handle) else {
+            continue;
+        };
+
+        commands.entity(root).despawn_descendants();
+        commands.entity(root).with_children(|parent| {
+            spawn_scene(parent, description, &mut assets, seed);
+        });
+
+        if scene.camera.is_none() {
+            if let Some(transform) = &description.camera {
+                let camera = commands
+                    .spawn(Camera3dBundle {
+                        transform: Transform::from(transform),
+                        ..Default::default()
+                    })
+                    .insert(FirstPersonCamera::default())
+                    .id();
+                scene.camera = Some(camera);
+            }
+        }
+        info!("Spawned scene {:?}", scene.handle.path());
+    }
+}
+
+/// Spawns the objects, forests and lights of `description` as children of `parent`. Forests are
+/// generated from `seed`, so that they are the same each time.
+fn spawn_scene(
+    parent: &mut ChildBuilder,
+    description: &SceneDescription,
+    assets: &mut SceneAssets,
+    seed: u64,
+) {
+    let materials: HashMap<&str, Handle<StandardMaterial>> = description
+        .materials
+        .iter()
+        .map(|(name, material)| {
+            let [r, g, b] = material.color;
+            let handle = assets.materials.add(StandardMaterial {
+                base_color: Color::rgb(r, g, b),
+                ..Default::default()
+            });
+            (name.as_str(), handle)
+        })
+        .collect();
+    let material = |name: &str| match materials.get(name) {
+        Some(handle) => handle.clone(),
+        None => {
+            warn!("Scene refers to unknown material '{}'", name);
+            Handle::default()
+        }
+    };
+
+    for object in description.objects.iter() {
+        let transform = Transform::from(&object.transform);
+        let mut entity = parent.spawn(PbrBundle {
+            mesh: assets.meshes.add(Mesh::from(&object.mesh)),
+            material: material(&object.material),
+            transform,
+            ..Default::default()
+        });
+        if object.spin != 0.0 {
+            entity.insert(Spin {
+                speed: object.spin,
+                rotation: transform.rotation,
+            });
+        }
+    }
+
+    for (index, forest) in description.forests.iter().enumerate() {
+        let mut rng = fastrand::Rng::with_seed(hash(seed ^ index as u64));
+        let crown_material = material(&forest.crown_material);
+        let trunk_material = material(&forest.trunk_material);
+        for tree in generate_forest(&ForestSettings::from(forest), &mut rng) {
+            spawn_tree(
+                parent,
+                &assets.tree_meshes,
+                &crown_material,
+                &trunk_material,
+                &tree,
+                TreeOptions::default(),
+            );
+        }
+    }
+
+    for light in description.lights.iter() {
+        match light {
+            LightDescription::Point {
+                transform,
+                intensity,
+                shadows,
+            } => {
+                let defaults = PointLight::default();
+                parent.spawn(PointLightBundle {
+                    point_light: PointLight {
+                        intensity: intensity.unwrap_or(defaults.intensity),
+                        shadows_enabled: *shadows,
+                        ..defaults
+                    },
+                    transform: Transform::from(transform),
+                    ..Default::default()
+                });
+            }
+            LightDescription::Directional {
+                transform,
+                illuminance,
+                shadows,
+            } => {
+                let defaults = DirectionalLight::default();
+                parent.spawn(DirectionalLightBundle {
+                    directional_light: DirectionalLight {
+                        illuminance: illuminance.unwrap_or(defaults.illuminance),
+                        shadows_enabled: *shadows,
+                        ..defaults
+                    },
+                    transform: Transform::from(transform),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+}
+
+fn spin_objects(time: Res<Time>, mut objects: Query<(&mut Transform, &Spin)>) {
+    for (mut transform, spin) in objects.iter_mut() {
+        transform.rotation =
+            spin.rotation * Quat::from_rotation_y(time.elapsed_seconds() * spin.speed);
+    }
+}
@@ -0,0 +1,88 @@
+//! Settings that demos save between runs. Each demo has its own file in a `bevy-demos` directory
+//! under the user's configuration directory. Files hold one `key = value` pair per line.
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use bevy::log::warn;
+
+/// Returns the path of the settings file named `file_name`, or `None` if the user's configuration
+/// directory cannot be determined.
+pub fn settings_path(file_name: &str) -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))?;
+
+    Some(config_dir.join("bevy-demos").join(file_name))
+}
+
+/// Returns the settings saved in the file named `file_name`. Returns no settings if the file
+/// doesn't exist or cannot be read.
+pub fn load_settings(file_name: &str) -> BTreeMap<String, String> {
+    let Some(path) = settings_path(file_name) else {
+        return BTreeMap::new();
+    };
+
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return BTreeMap::new(),
+        Err(e) => {
+            warn!("Cannot read settings from {}: {}", path.display(), e);
+            return BTreeMap::new();
+        }
+    };
+
+    parse_key_values(&contents, &path.display().to_string())
+}
+
+/// Returns the `key = value` pairs in `contents`, which was read from `source`. Blank lines and
+/// lines starting with `#` are ignored. Other lines without an `=` are logged and ignored.
+pub fn parse_key_values(contents: &str, source: &str) -> BTreeMap<String, String> {
+    let mut values = BTreeMap::new();
+
+    for line in contents.lines() {
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        if let Some((key, value)) = line.split_once('=') {
+            values.insert(key.trim().to_string(), value.trim().to_string());
+        } else {
+            warn!("Ignoring malformed line in {}: {}", source, line);
+        }
+    }
+    values
+}
+
+/// Saves `settings` to the file named `file_name`, replacing its previous contents.
+pub fn save_settings(file_name: &str, settings: &BTreeMap<String, String>) -> io::Result<()> {
+    let path = settings_path(file_name).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "cannot determine the configuration directory",
+        )
+    })?;
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let contents: String = settings
+        .iter()
+        .map(|(key, value)| format!("{} = {}\n", key, value))
+        .collect();
+    fs::write(path, contents)
+}
+
+/// Sets `key` to `value` in the file named `file_name`, keeping any other settings in the file.
+/// Logs a warning if the file cannot be written.
+pub fn save_setting(file_name: &str, key: &str, value: &str) {
+    let mut settings = load_settings(file_name);
+    settings.insert(key.to_string(), value.to_string());
+
+    if let Err(e) = save_settings(file_name, &settings) {
+        warn!("Cannot save settings to {}: {}", file_name, e);
+    }
+}
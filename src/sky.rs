@@ -0,0 +1,220 @@
+//! A sky that fades from one color at the horizon to another overhead, and distance fog of the
+//! horizon color, so that distant terrain and trees fade into the sky rather than ending abruptly.
+//! The colors are set in the `Sky` resource, which `DayNightPlugin` updates through the day. The
+//! fog distance can be changed, and the fog turned off, with the keys in `SkySettings`.
+use bevy::pbr::{NotShadowCaster, NotShadowReceiver};
+use bevy::prelude::*;
+use bevy::render::mesh::VertexAttributeValues;
+
+use crate::key_bindings::rebinding_screen_closed;
+
+/// The radius of the sphere the sky is drawn on. It must be within the camera's far plane.
+const SKY_RADIUS: f32 = 500.0;
+
+/// The shortest distance the fog can be made to end at, and the longest.
+const MIN_FOG_END: f32 = 20.0;
+const MAX_FOG_END: f32 = 400.0;
+
+/// The colors of the sky.
+#[derive(Clone, Debug, Resource)]
+pub struct Sky {
+    /// The color at and below the horizon. The fog is this color, so that it blends into the sky.
+    pub horizon: Color,
+    /// The color straight overhead.
+    pub zenith: Color,
+}
+
+impl Default for Sky {
+    fn default() -> Self {
+        Self {
+            horizon: Color::rgb(0.7, 0.8, 0.95),
+            zenith: Color::rgb(0.25, 0.45, 0.85),
+        }
+    }
+}
+
+/// The distance fog, and the keys that change it.
+#[derive(Clone, Debug, Resource)]
+pub struct SkySettings {
+    pub fog_enabled: bool,
+    /// The distance from the camera at which the fog starts.
+    pub fog_start: f32,
+    /// The distance from the camera beyond which nothing can be seen through the fog.
+    pub fog_end: f32,
+    /// The key that turns the fog on and off.
+    pub toggle_fog_key: KeyCode,
+    /// The key that brings the fog closer.
+    pub nearer_fog_key: KeyCode,
+    /// The key that moves the fog further away.
+    pub further_fog_key: KeyCode,
+}
+
+impl Default for SkySettings {
+    fn default() -> Self {
+        Self {
+            fog_enabled: true,
+            fog_start: 30.0,
+            fog_end: 90.0,
+            toggle_fog_key: KeyCode::Key0,
+            nearer_fog_key: KeyCode::Minus,
+            further_fog_key: KeyCode::Equals,
+        }
+    }
+}
+
+/// Marks the sphere the sky is drawn on.
+#[derive(Component)]
+struct SkyDome;
+
+/// Adds `Sky` and `SkySettings`, and the systems that draw the sky around 3D cameras and give
+/// them fog.
+pub struct SkyPlugin;
+
+impl Plugin for SkyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Sky>()
+            .init_resource::<SkySettings>()
+            .add_systems(Startup, spawn_sky_dome)
+            .add_systems(
+                Update,
+                (
+                    adjust_fog.run_if(rebinding_screen_closed),
+                    add_fog,
+                    apply_deferred,
+                    update_fog,
+                    color_sky_dome,
+                    follow_camera,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Spawns the sphere the sky is drawn on. It is seen from the inside, isn't lit, and isn't
+/// affected by fog.
+fn spawn_sky_dome(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.spawn((
+        PbrBundle {
+            mesh: meshes.add(Mesh::from(shape::UVSphere {
+                radius: SKY_RADIUS,
+                sectors: 32,
+                stacks: 16,
+            })),
+            material: materials.add(StandardMaterial {
+                base_color: Color::WHITE,
+                unlit: true,
+                fog_enabled: false,
+                cull_mode: None,
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        SkyDome,
+        NotShadowCaster,
+        NotShadowReceiver,
+    ));
+}
+
+/// Turns the fog on and off, and moves it nearer and further, when the keys in `SkySettings` are
+/// pressed. This doesn't run while the rebinding screen is open, so binding one of the keys
+/// doesn't also change the fog.
+fn adjust_fog(key: Res<Input<KeyCode>>, mut settings: ResMut<SkySettings>) {
+    if key.just_pressed(settings.toggle_fog_key) {
+        settings.fog_enabled = !settings.fog_enabled;
+    }
+
+    let scale = if key.just_pressed(settings.nearer_fog_key) {
+        0.8
+    } else if key.just_pressed(settings.further_fog_key) {
+        1.25
+    } else {
+        return;
+    };
+    let fog_end = (settings.fog_end * scale).clamp(MIN_FOG_END, MAX_FOG_END);
+    let fog_start = settings.fog_start * fog_end / settings.fog_end;
+    settings.fog_start = fog_start;
+    settings.fog_end = fog_end;
+}
+
+/// Gives fog to 3D cameras that don't have any.
+fn add_fog(mut commands: Commands, cameras: Query<Entity, (With<Camera3d>, Without<FogSettings>)>) {
+    for entity in cameras.iter() {
+        commands.entity(entity).insert(FogSettings::default());
+    }
+}
+
+/// Sets the fog of 3D cameras from `SkySettings` and the horizon color of the `Sky`. Turning the
+/// fog off moves it beyond the far side of the sky.
+fn update_fog(
+    sky: Res<Sky>,
+    settings: Res<SkySettings>,
+    mut fogs: Query<&mut FogSettings, With<Camera3d>>,
+    mut clear_color: ResMut<ClearColor>,
+) {
+    if !sky.is_changed() && !settings.is_changed() && !fogs.iter_mut().any(|f| f.is_added()) {
+        return;
+    }
+
+    let (start, end) = if settings.fog_enabled {
+        (settings.fog_start, settings.fog_end)
+    } else {
+        (SKY_RADIUS * 2.0, SKY_RADIUS * 3.0)
+    };
+    for mut fog in fogs.iter_mut() {
+        fog.color = sky.horizon;
+        fog.falloff = FogFalloff::Linear { start, end };
+    }
+    clear_color.0 = sky.horizon;
+}
+
+/// Colors the sky dome with the colors of the `Sky`, blending from the horizon color at the
+/// horizon to the zenith color overhead.
+fn color_sky_dome(
+    sky: Res<Sky>,
+    domes: Query<&Handle<Mesh>, With<SkyDome>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    if !sky.is_changed() {
+        return;
+    }
+
+    let horizon = Vec4::from(sky.horizon.as_linear_rgba_f32());
+    let zenith = Vec4::from(sky.zenith.as_linear_rgba_f32());
+    for handle in domes.iter() {
+        let Some(mesh) = meshes.get_mut(handle) else {
+            continue;
+        };
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            continue;
+        };
+
+        // The square root keeps the horizon color close to the horizon.
+        let colors: Vec<[f32; 4]> = positions
+            .iter()
+            .map(|p| {
+                let height = (p[1] / SKY_RADIUS).max(0.0).sqrt();
+                horizon.lerp(zenith, height).to_array()
+            })
+            .collect();
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    }
+}
+
+/// Keeps the sky dome centered on the camera, so it can never be reached.
+fn follow_camera(
+    cameras: Query<&Transform, (With<Camera3d>, Without<SkyDome>)>,
+    mut domes: Query<&mut Transform, With<SkyDome>>,
+) {
+    let Ok(camera) = cameras.get_single() else {
+        return;
+    };
+    for mut dome in domes.iter_mut() {
+        dome.translation = camera.translation;
+    }
+}
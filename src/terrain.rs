@@ -0,0 +1,216 @@
+//! Hilly ground generated from noise. `TerrainNoise` gives the height of the ground anywhere, so
+//! the ground can be built in pieces that line up exactly. A `Terrain` is a grid of heights taken
+//! from the noise over a rectangle, which can be turned into a `Mesh` and queried for the height at
+//! any point, e.g., to stand trees on it. Walking `FirstPersonCamera`s stand on entities with a
+//! `Terrain` component.
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, Mesh};
+use bevy::render::render_resource::PrimitiveTopology;
+
+use crate::rng::hash;
+
+/// Describes the shape of the hills.
+#[derive(Clone, Debug)]
+pub struct TerrainSettings {
+    /// The height of the highest possible hill. The lowest possible valley is at zero.
+    pub max_height: f32,
+    /// The distance between the largest hills.
+    pub feature_size: f32,
+    /// The number of layers of noise. Each layer adds hills half the size and height of the one
+    /// before, making the terrain rougher.
+    pub octaves: u32,
+}
+
+impl Default for TerrainSettings {
+    fn default() -> Self {
+        Self {
+            max_height: 6.0,
+            feature_size: 30.0,
+            octaves: 4,
+        }
+    }
+}
+
+/// Fractal value noise covering the whole XZ plane. The height at any point depends only on the
+/// point, the settings and the seed, so terrain generated in separate pieces is seamless.
+#[derive(Clone, Debug)]
+pub struct TerrainNoise {
+    settings: TerrainSettings,
+    seed: u64,
+}
+
+impl TerrainNoise {
+    pub fn new(settings: TerrainSettings, seed: u64) -> Self {
+        Self { settings, seed }
+    }
+
+    /// Returns the height of the noise at `x`, `z`, in 0..max_height.
+    pub fn height_at(&self, x: f32, z: f32) -> f32 {
+        let mut height = 0.0;
+        let mut amplitude = 1.0;
+        let mut total_amplitude = 0.0;
+        let mut feature_size = self.settings.feature_size;
+        for octave in 0..self.settings.octaves {
+            height += amplitude * self.value_noise(octave, x / feature_size, z / feature_size);
+            total_amplitude += amplitude;
+            amplitude /= 2.0;
+            feature_size /= 2.0;
+        }
+
+        // Noise values are in 0..1, so dividing by the total amplitude keeps heights within
+        // 0..max_height.
+        height * self.settings.max_height / total_amplitude.max(1.0)
+    }
+
+    /// Returns random values in 0..1 at integer coordinates, smoothly interpolated in between.
+    /// Each octave has different values.
+    fn value_noise(&self, octave: u32, x: f32, z: f32) -> f32 {
+        let (x0, z0) = (x.floor(), z.floor());
+        let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+        let (tx, tz) = (smooth(x - x0), smooth(z - z0));
+
+        let (x0, z0) = (x0 as i64, z0 as i64);
+        let value = |x: i64, z: i64| self.lattice_value(octave, x, z);
+        let back = value(x0, z0) + tx * (value(x0 + 1, z0) - value(x0, z0));
+        let front = value(x0, z0 + 1) + tx * (value(x0 + 1, z0 + 1) - value(x0, z0 + 1));
+        back + tz * (front - back)
+    }
+
+    /// Returns a random value in 0..1 for the lattice point `x`, `z` of `octave`.
+    fn lattice_value(&self, octave: u32, x: i64, z: i64) -> f32 {
+        let hash = hash(hash(hash(self.seed ^ octave as u64) ^ x as u64) ^ z as u64);
+        (hash >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+/// A grid of heights over a rectangle of the XZ plane. Heights between the grid points are
+/// interpolated across the same triangles as the terrain's mesh, so things placed using
+/// `height_at` sit exactly on the surface.
+#[derive(Clone, Component, Debug)]
+pub struct Terrain {
+    /// The area covered, with `x` and `y` of the rectangle giving the X and Z coordinates.
+    area: Rect,
+    /// The number of grid points along each side.
+    resolution: usize,
+    /// The heights of the grid points, row by row from -Z to +Z, each row from -X to +X.
+    heights: Vec<f32>,
+    /// The surface normals at the grid points, in the same order as `heights`.
+    normals: Vec<Vec3>,
+}
+
+impl Terrain {
+    /// Returns terrain covering `area` with `resolution` grid points along each side, taking its
+    /// heights from `noise`.
+    pub fn from_noise(noise: &TerrainNoise, area: Rect, resolution: usize) -> Self {
+        let resolution = resolution.max(2);
+        let spacing = area.size() / (resolution - 1) as f32;
+        let position = |column: f32, row: f32| area.min + Vec2::new(column, row) * spacing;
+
+        let mut heights = Vec::with_capacity(resolution * resolution);
+        let mut normals = Vec::with_capacity(resolution * resolution);
+        for row in 0..resolution {
+            for column in 0..resolution {
+                let (column, row) = (column as f32, row as f32);
+                let p = position(column, row);
+                heights.push(noise.height_at(p.x, p.y));
+
+                // The normal is perpendicular to the slope, found from the heights of the
+                // neighbouring grid points. These are taken from the noise even beyond the edges,
+                // so the lighting of neighbouring pieces of terrain matches.
+                let height = |column: f32, row: f32| {
+                    let p = position(column, row);
+                    noise.height_at(p.x, p.y)
+                };
+                let dx =
+                    (height(column + 1.0, row) - height(column - 1.0, row)) / (2.0 * spacing.x);
+                let dz =
+                    (height(column, row + 1.0) - height(column, row - 1.0)) / (2.0 * spacing.y);
+                normals.push(Vec3::new(-dx, 1.0, -dz).normalize());
+            }
+        }
+
+        Self {
+            area,
+            resolution,
+            heights,
+            normals,
+        }
+    }
+
+    /// Returns the area covered, with `x` and `y` of the rectangle giving the X and Z coordinates.
+    pub fn area(&self) -> Rect {
+        self.area
+    }
+
+    /// Returns `true` if `x`, `z` is within the area covered.
+    pub fn contains(&self, x: f32, z: f32) -> bool {
+        self.area.contains(Vec2::new(x, z))
+    }
+
+    /// Returns the height of the grid point in `column` (from -X) and `row` (from -Z).
+    fn grid_height(&self, column: usize, row: usize) -> f32 {
+        self.heights[row * self.resolution + column]
+    }
+
+    /// Returns the height of the surface at `x`, `z`. Points beyond the edges take the height of
+    /// the nearest edge.
+    pub fn height_at(&self, x: f32, z: f32) -> f32 {
+        let cells = (self.resolution - 1) as f32;
+        let grid = ((Vec2::new(x, z) - self.area.min) / self.area.size() * cells)
+            .clamp(Vec2::ZERO, Vec2::splat(cells));
+
+        let column = (grid.x as usize).min(self.resolution - 2);
+        let row = (grid.y as usize).min(self.resolution - 2);
+        let (fx, fz) = (grid.x - column as f32, grid.y - row as f32);
+
+        // Each cell is split into two triangles along the diagonal from its +X -Z corner to its
+        // -X +Z corner, matching the mesh.
+        let a = self.grid_height(column, row);
+        let b = self.grid_height(column + 1, row);
+        let c = self.grid_height(column, row + 1);
+        let d = self.grid_height(column + 1, row + 1);
+        if fx + fz <= 1.0 {
+            a + fx * (b - a) + fz * (c - a)
+        } else {
+            d + (1.0 - fx) * (c - d) + (1.0 - fz) * (b - d)
+        }
+    }
+}
+
+impl From<&Terrain> for Mesh {
+    fn from(terrain: &Terrain) -> Self {
+        let resolution = terrain.resolution;
+        let last = resolution - 1;
+        let spacing = terrain.area.size() / last as f32;
+
+        let mut positions = Vec::with_capacity(resolution * resolution);
+        let mut uvs = Vec::with_capacity(resolution * resolution);
+        for row in 0..resolution {
+            for column in 0..resolution {
+                let p = terrain.area.min + Vec2::new(column as f32, row as f32) * spacing;
+                positions.push([p.x, terrain.grid_height(column, row), p.y]);
+                uvs.push([column as f32 / last as f32, row as f32 / last as f32]);
+            }
+        }
+        let normals: Vec<[f32; 3]> = terrain.normals.iter().map(|n| n.to_array()).collect();
+
+        // Two triangles for each cell, counter-clockwise when seen from above.
+        let mut indices = Vec::with_capacity(last * last * 6);
+        for row in 0..last {
+            for column in 0..last {
+                let a = (row * resolution + column) as u32;
+                let b = a + 1;
+                let c = a + resolution as u32;
+                let d = c + 1;
+                indices.extend([a, c, b, b, c, d]);
+            }
+        }
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh
+    }
+}
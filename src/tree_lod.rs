@@ -0,0 +1,550 @@
+//! Level of detail for trees, so that large forests can be drawn quickly. Each tree is drawn in one
+//! of four ways, chosen by its distance from the camera:
+//!
+//! 0. A cube for the trunk and a pyramid with `CROWN_SIDES` sides for the crown.
+//! 1. As 0, but with a four-sided pyramid for the crown.
+//! 2. A single mesh combining a trunk and a four-sided crown, colored by its vertexes, so the tree
+//!    is one entity to draw rather than two.
+//! 3. A flat outline of a tree that always faces the camera.
+//!
+//! A tree only moves to a different level once it is `hysteresis` beyond the distance between the
+//! levels, so that trees near that distance don't flicker between levels as the camera moves.
+//!
+//! The meshes of levels 2 and 3 are made for each height of trunk in use, to the nearest percent of
+//! the tree's height, so that the top of the trunk stays where it is when a tree changes level. They
+//! are colored with the base colors of the tree's crown and trunk materials, and remade when those
+//! materials change.
+//!
+//! Trees are spawned by `spawn_tree` as entities with a `TreeLod` component and a `SpatialBundle`,
+//! whose children are the trunk and crown, marked with `TreePart`. The trunk and crown use the
+//! meshes in `TreeMeshes`, scaled by their `Transform`s.
+use bevy::ecs::system::{EntityCommands, SystemParam};
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, Mesh, PrimitiveTopology, VertexAttributeValues};
+use bevy::utils::HashMap;
+
+use crate::first_person_camera::Collider;
+use crate::forest::{Tree, TreeMeshes, CROWN_SIDES};
+use crate::pyramid::Pyramid;
+
+/// The number of sides of the crown from level 1 on.
+const LOW_DETAIL_SIDES: u32 = 4;
+
+/// The first level drawn with a single mesh rather than a separate trunk and crown.
+const MERGED_LEVEL: usize = 2;
+
+/// The level drawn as a flat outline facing the camera.
+const BILLBOARD_LEVEL: usize = 3;
+
+/// The width of the trunk of merged and billboard trees, as a fraction of the crown's width. This
+/// is about the average for the trees generated by `generate_forest`. Unlike the trunk's height, it
+/// isn't matched to each tree, as the difference is too small to see at these levels' distances.
+const TRUNK_WIDTH_FRACTION: f32 = 0.37;
+
+/// The distances from the camera at which trees change level.
+#[derive(Clone, Debug, Resource)]
+pub struct TreeLodSettings {
+    /// Trees further than `distances[n]` are drawn at level `n + 1` or higher.
+    pub distances: [f32; 3],
+    /// The fraction of a distance in `distances` that a tree must be beyond it before it changes
+    /// level, in either direction.
+    pub hysteresis: f32,
+}
+
+impl Default for TreeLodSettings {
+    fn default() -> Self {
+        Self {
+            distances: [25.0, 50.0, 100.0],
+            hysteresis: 0.1,
+        }
+    }
+}
+
+impl TreeLodSettings {
+    /// Returns the level of a tree at `distance` from the camera that was previously at `level`,
+    /// or at no level yet if `level` is `None`.
+    fn level(&self, level: Option<usize>, distance: f32) -> usize {
+        let Some(mut level) = level else {
+            return self.distances.iter().filter(|&&d| distance > d).count();
+        };
+
+        while level < self.distances.len()
+            && distance > self.distances[level] * (1.0 + self.hysteresis)
+        {
+            level += 1;
+        }
+        while level > 0 && distance < self.distances[level - 1] * (1.0 - self.hysteresis) {
+            level -= 1;
+        }
+        level
+    }
+}
+
+/// Marks a tree whose level of detail is chosen by its distance from the camera.
+#[derive(Clone, Component, Debug)]
+pub struct TreeLod {
+    /// The scale that fits the merged and billboard meshes to the tree.
+    size: Vec3,
+    /// The height of the trunk as a percentage of the tree's height, which chooses the merged and
+    /// billboard meshes the tree is drawn with.
+    trunk_percent: u8,
+    /// The materials of the crown and trunk, whose colors the merged and billboard meshes take.
+    crown_material: Handle<StandardMaterial>,
+    trunk_material: Handle<StandardMaterial>,
+    level: Option<usize>,
+}
+
+impl TreeLod {
+    /// Returns the component for `tree`, whose crown and trunk are drawn with `crown_material` and
+    /// `trunk_material`.
+    pub fn new(
+        tree: &Tree,
+        crown_material: &Handle<StandardMaterial>,
+        trunk_material: &Handle<StandardMaterial>,
+    ) -> Self {
+        let height = tree.trunk_height + tree.crown_height;
+        Self {
+            size: Vec3::new(tree.crown_width, height, tree.crown_width),
+            trunk_percent: (tree.trunk_height / height * 100.0)
+                .round()
+                .clamp(1.0, 99.0) as u8,
+            crown_material: crown_material.clone(),
+            trunk_material: trunk_material.clone(),
+            level: None,
+        }
+    }
+
+    /// Returns the level the tree is drawn at, or `None` if it hasn't been drawn yet.
+    pub fn level(&self) -> Option<usize> {
+        self.level
+    }
+}
+
+/// Marks the children of a `TreeLod` entity with the part of the tree they draw. The `Impostor`,
+/// which draws the merged and billboard levels, is added by `TreeLodPlugin`.
+#[derive(Clone, Copy, Component, Debug, Eq, PartialEq)]
+pub enum TreePart {
+    Trunk,
+    Crown,
+    Impostor,
+}
+
+/// What `spawn_tree` adds to a tree besides its trunk and crown.
+#[derive(Clone, Copy, Debug)]
+pub struct TreeOptions {
+    /// Whether to add a `TreeLod` component, so the tree's level of detail is chosen by
+    /// `TreeLodPlugin`.
+    pub lod: bool,
+    /// Whether to give the trunk and crown `Collider`s, a cylinder around the trunk and a cone
+    /// around the crown, so a `FirstPersonCamera` can't pass through them.
+    pub colliders: bool,
+    /// Whether to scale the trunk and crown meshes to the tree's dimensions. Turn this off to use
+    /// meshes that were built at the tree's size rather than the unit meshes of `TreeMeshes`. The
+    /// lower levels of detail replace the meshes with scaled ones, so this must be on if `lod` is.
+    pub scale_meshes: bool,
+}
+
+impl Default for TreeOptions {
+    fn default() -> Self {
+        Self {
+            lod: true,
+            colliders: false,
+            scale_meshes: true,
+        }
+    }
+}
+
+/// Spawns `tree` as a child of `parent`, with the bottom of its trunk at the tree's location. The
+/// trunk is the cube and the crown the pyramid in `tree_meshes`, scaled to the dimensions in `tree`
+/// unless `options` says otherwise, and drawn with `trunk_material` and `crown_material`. Returns
+/// the commands for the tree's root entity, so callers can add components of their own.
+pub fn spawn_tree<'w, 's, 'a>(
+    parent: &'a mut ChildBuilder<'w, 's, '_>,
+    tree_meshes: &TreeMeshes,
+    crown_material: &Handle<StandardMaterial>,
+    trunk_material: &Handle<StandardMaterial>,
+    tree: &Tree,
+    options: TreeOptions,
+) -> EntityCommands<'w, 's, 'a> {
+    let mut root = parent.spawn(SpatialBundle::from_transform(Transform::from_translation(
+        tree.location,
+    )));
+    if options.lod {
+        root.insert(TreeLod::new(tree, crown_material, trunk_material));
+    }
+
+    let scale = |size| {
+        if options.scale_meshes {
+            size
+        } else {
+            Vec3::ONE
+        }
+    };
+    root.with_children(|parent| {
+        let mut trunk = parent.spawn((
+            PbrBundle {
+                mesh: tree_meshes.trunk.clone(),
+                material: trunk_material.clone(),
+                transform: Transform::from_xyz(0., tree.trunk_height / 2., 0.).with_scale(scale(
+                    Vec3::new(tree.trunk_width, tree.trunk_height, tree.trunk_width),
+                )),
+                ..Default::default()
+            },
+            TreePart::Trunk,
+        ));
+        if options.colliders {
+            // The collider reaches the corners of the box.
+            trunk.insert(Collider::Cylinder {
+                radius: tree.trunk_width * std::f32::consts::FRAC_1_SQRT_2,
+                height: tree.trunk_height,
+            });
+        }
+
+        let mut crown = parent.spawn((
+            PbrBundle {
+                mesh: tree_meshes.crown.clone(),
+                material: crown_material.clone(),
+                transform: Transform::from_xyz(0., tree.trunk_height, 0.).with_scale(scale(
+                    Vec3::new(tree.crown_width, tree.crown_height, tree.crown_width),
+                )),
+                ..Default::default()
+            },
+            TreePart::Crown,
+        ));
+        if options.colliders {
+            // The collider reaches the corners of the pyramid's base.
+            crown.insert(Collider::Cone {
+                radius: Pyramid::new(CROWN_SIDES, tree.crown_width, tree.crown_height).radius(),
+                height: tree.crown_height,
+            });
+        }
+    });
+    root
+}
+
+/// Identifies the merged and billboard meshes a tree is drawn with, by the materials of its crown
+/// and trunk, and `TreeLod::trunk_percent`.
+type ImpostorKey = (AssetId<StandardMaterial>, AssetId<StandardMaterial>, u8);
+
+/// The meshes and material for the lower levels of detail.
+#[derive(Clone, Debug, Resource)]
+struct TreeLodMeshes {
+    crown: Handle<Mesh>,
+    /// The merged and billboard meshes for each pair of materials and height of trunk. They are
+    /// made when a tree first needs them.
+    impostors: HashMap<ImpostorKey, ImpostorMeshes>,
+    /// A white material, so the vertex colors of the merged and billboard meshes show.
+    material: Handle<StandardMaterial>,
+}
+
+/// The assets used to make the merged and billboard meshes of trees.
+#[derive(SystemParam)]
+struct ImpostorAssets<'w> {
+    lod_meshes: ResMut<'w, TreeLodMeshes>,
+    meshes: ResMut<'w, Assets<Mesh>>,
+    materials: Res<'w, Assets<StandardMaterial>>,
+}
+
+impl ImpostorAssets<'_> {
+    /// Returns the merged and billboard meshes for the tree with `lod`, making them if no tree with
+    /// the same materials and height of trunk has needed them yet.
+    fn impostor(&mut self, lod: &TreeLod) -> ImpostorMeshes {
+        let color = |material| {
+            self.materials
+                .get(material)
+                .map_or(Color::WHITE, |material| material.base_color)
+        };
+        let (crown_color, trunk_color) = (color(&lod.crown_material), color(&lod.trunk_material));
+        let key = (
+            lod.crown_material.id(),
+            lod.trunk_material.id(),
+            lod.trunk_percent,
+        );
+
+        let meshes = &mut self.meshes;
+        self.lod_meshes
+            .impostors
+            .entry(key)
+            .or_insert_with(|| {
+                let trunk_fraction = lod.trunk_percent as f32 / 100.0;
+                ImpostorMeshes {
+                    merged: meshes.add(merged_mesh(trunk_fraction, crown_color, trunk_color)),
+                    billboard: meshes.add(billboard_mesh(trunk_fraction, crown_color, trunk_color)),
+                }
+            })
+            .clone()
+    }
+}
+
+/// The meshes of the merged and billboard levels for trees with one height of trunk.
+#[derive(Clone, Debug)]
+struct ImpostorMeshes {
+    merged: Handle<Mesh>,
+    billboard: Handle<Mesh>,
+}
+
+/// Adds `TreeLodSettings` and the systems that choose how each tree with a `TreeLod` component is
+/// drawn. Must be added after `DefaultPlugins`.
+pub struct TreeLodPlugin;
+
+impl Plugin for TreeLodPlugin {
+    fn build(&self, app: &mut App) {
+        let crown = app
+            .world
+            .resource_mut::<Assets<Mesh>>()
+            .add(Mesh::from(Pyramid::from_radius(
+                LOW_DETAIL_SIDES,
+                unit_crown_radius(),
+                1.0,
+            )));
+
+        let material = app
+            .world
+            .resource_mut::<Assets<StandardMaterial>>()
+            .add(StandardMaterial::from(Color::WHITE));
+
+        app.init_resource::<TreeMeshes>()
+            .init_resource::<TreeLodSettings>()
+            .insert_resource(TreeLodMeshes {
+                crown,
+                impostors: HashMap::new(),
+                material,
+            })
+            .add_systems(
+                Update,
+                (
+                    recolor_impostors,
+                    add_impostors,
+                    apply_deferred,
+                    update_tree_lods,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Returns the radius of the base of the crown in `TreeMeshes`, so that the lower levels of detail
+/// can match its size.
+fn unit_crown_radius() -> f32 {
+    Pyramid::new(CROWN_SIDES, 1.0, 1.0).radius()
+}
+
+/// Returns a tree of unit height and crown width made of a box for the trunk and a four-sided
+/// pyramid for the crown, whose trunk is `trunk_fraction` of its height.
+fn merged_mesh(trunk_fraction: f32, crown_color: Color, trunk_color: Color) -> Mesh {
+    merge_meshes(&[
+        (
+            Mesh::from(shape::Box::new(
+                TRUNK_WIDTH_FRACTION,
+                trunk_fraction,
+                TRUNK_WIDTH_FRACTION,
+            )),
+            Vec3::new(0.0, trunk_fraction / 2.0, 0.0),
+            trunk_color,
+        ),
+        (
+            Mesh::from(Pyramid::from_radius(
+                LOW_DETAIL_SIDES,
+                unit_crown_radius(),
+                1.0 - trunk_fraction,
+            )),
+            Vec3::new(0.0, trunk_fraction, 0.0),
+            crown_color,
+        ),
+    ])
+}
+
+/// Returns a single mesh made of `parts`, each moved by its offset and colored with its color, so
+/// that parts of different colors can be drawn with one material.
+fn merge_meshes(parts: &[(Mesh, Vec3, Color)]) -> Mesh {
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut colors = Vec::new();
+    let mut indices = Vec::new();
+
+    for (mesh, offset, color) in parts {
+        let first = positions.len() as u32;
+        let part_positions = mesh
+            .attribute(Mesh::ATTRIBUTE_POSITION)
+            .and_then(VertexAttributeValues::as_float3)
+            .unwrap_or_default();
+        let part_normals = mesh
+            .attribute(Mesh::ATTRIBUTE_NORMAL)
+            .and_then(VertexAttributeValues::as_float3)
+            .unwrap_or_default();
+
+        for (position, normal) in part_positions.iter().zip(part_normals) {
+            positions.push((Vec3::from(*position) + *offset).to_array());
+            normals.push(*normal);
+            colors.push(color.as_linear_rgba_f32());
+        }
+        if let Some(part_indices) = mesh.indices() {
+            indices.extend(part_indices.iter().map(|i| first + i as u32));
+        }
+    }
+
+    let uvs = vec![[0.0, 0.0]; positions.len()];
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+/// Returns the outline of a tree of unit height and crown width in the XY plane, facing +Z: a
+/// rectangle for the trunk, `trunk_fraction` of the tree's height, and a triangle for the crown.
+fn billboard_mesh(trunk_fraction: f32, crown_color: Color, trunk_color: Color) -> Mesh {
+    let crown_radius = unit_crown_radius();
+    let trunk = TRUNK_WIDTH_FRACTION / 2.0;
+    let top = trunk_fraction;
+    let positions = vec![
+        [-trunk, 0.0, 0.0],
+        [trunk, 0.0, 0.0],
+        [trunk, top, 0.0],
+        [-trunk, top, 0.0],
+        [-crown_radius, top, 0.0],
+        [crown_radius, top, 0.0],
+        [0.0, 1.0, 0.0],
+    ];
+    let trunk_color = trunk_color.as_linear_rgba_f32();
+    let crown_color = crown_color.as_linear_rgba_f32();
+    let colors = vec![
+        trunk_color,
+        trunk_color,
+        trunk_color,
+        trunk_color,
+        crown_color,
+        crown_color,
+        crown_color,
+    ];
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0.0, 0.0, 1.0]; 7]);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0.0, 0.0]; 7]);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    mesh.set_indices(Some(Indices::U32(vec![0, 1, 2, 0, 2, 3, 4, 5, 6])));
+    mesh
+}
+
+/// Forgets the merged and billboard meshes made with materials that have changed or been removed,
+/// and redraws the trees whose materials changed, so that they are recolored at every level.
+fn recolor_impostors(
+    mut events: EventReader<AssetEvent<StandardMaterial>>,
+    mut lod_meshes: ResMut<TreeLodMeshes>,
+    mut trees: Query<&mut TreeLod>,
+) {
+    for event in events.read() {
+        let (AssetEvent::Modified { id } | AssetEvent::Removed { id }) = *event else {
+            continue;
+        };
+
+        lod_meshes
+            .impostors
+            .retain(|&(crown, trunk, _), _| crown != id && trunk != id);
+        for mut lod in trees.iter_mut() {
+            if lod.crown_material.id() == id || lod.trunk_material.id() == id {
+                lod.level = None;
+            }
+        }
+    }
+}
+
+/// Adds a hidden impostor to each new tree, which draws the tree at the merged and billboard
+/// levels.
+fn add_impostors(
+    mut commands: Commands,
+    mut assets: ImpostorAssets,
+    trees: Query<(Entity, &TreeLod), Added<TreeLod>>,
+) {
+    for (entity, lod) in trees.iter() {
+        let impostor = assets.impostor(lod);
+        commands.entity(entity).with_children(|parent| {
+            parent
+                .spawn(PbrBundle {
+                    mesh: impostor.merged,
+                    material: assets.lod_meshes.material.clone(),
+                    transform: Transform::from_scale(lod.size),
+                    visibility: Visibility::Hidden,
+                    ..Default::default()
+                })
+                .insert(TreePart::Impostor);
+        });
+    }
+}
+
+/// Moves trees to the level for their distance from the camera, showing and hiding their parts
+/// and switching meshes to match. Also turns billboard trees to face the camera.
+fn update_tree_lods(
+    settings: Res<TreeLodSettings>,
+    tree_meshes: Res<TreeMeshes>,
+    mut assets: ImpostorAssets,
+    cameras: Query<&GlobalTransform, With<Camera3d>>,
+    mut trees: Query<(&mut TreeLod, &GlobalTransform, &Children)>,
+    mut parts: Query<(
+        &TreePart,
+        &mut Visibility,
+        &mut Handle<Mesh>,
+        &mut Transform,
+    )>,
+) {
+    let Ok(camera) = cameras.get_single() else {
+        return;
+    };
+
+    for (mut lod, transform, children) in trees.iter_mut() {
+        let offset = camera.translation() - transform.translation();
+        let level = settings.level(lod.level, offset.length());
+        let changed = lod.level != Some(level);
+        if !changed && level != BILLBOARD_LEVEL {
+            continue;
+        }
+        lod.level = Some(level);
+
+        let visible_if = |visible| {
+            if visible {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            }
+        };
+
+        let mut tree_parts = parts.iter_many_mut(children);
+        while let Some((part, mut visibility, mut mesh, mut part_transform)) =
+            tree_parts.fetch_next()
+        {
+            match part {
+                TreePart::Trunk if changed => *visibility = visible_if(level < MERGED_LEVEL),
+                TreePart::Crown if changed => {
+                    *visibility = visible_if(level < MERGED_LEVEL);
+                    if level < MERGED_LEVEL {
+                        *mesh = if level == 0 {
+                            tree_meshes.crown.clone()
+                        } else {
+                            assets.lod_meshes.crown.clone()
+                        };
+                    }
+                }
+                TreePart::Impostor => {
+                    if changed {
+                        *visibility = visible_if(level >= MERGED_LEVEL);
+                        let impostor = assets.impostor(&lod);
+                        *mesh = if level == BILLBOARD_LEVEL {
+                            impostor.billboard
+                        } else {
+                            impostor.merged
+                        };
+                    }
+                    part_transform.rotation = if level == BILLBOARD_LEVEL {
+                        Quat::from_rotation_y(offset.x.atan2(offset.z))
+                    } else {
+                        Quat::IDENTITY
+                    };
+                }
+                _ => {}
+            }
+        }
+    }
+}